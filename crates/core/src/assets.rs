@@ -0,0 +1,182 @@
+//! Runtime assets for **htms**.
+//!
+//! The chunk runtime (`htms_chunk.js`) and its stylesheet (`style.css`) are either inlined into
+//! every compiled template or referenced as external, cacheable files. In the external mode the
+//! file names embed a content hash and the tags carry a Subresource Integrity attribute, so the
+//! files can be served with immutable cache headers (see `axum::assets_router` with the `axum`
+//! feature).
+//!
+//! # Example
+//! ```rust
+//! use htms_core::assets::{HTMS_CHUNK_JS, STYLE_CSS};
+//!
+//! assert!(HTMS_CHUNK_JS.path().starts_with("/_htms/htms_chunk."));
+//! assert!(STYLE_CSS.integrity().starts_with("sha384-"));
+//! ```
+
+use std::{fmt::Write, str::FromStr, sync::LazyLock};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use sha2::{Digest, Sha384};
+
+/// URL path prefix under which the external assets are served.
+pub const ASSETS_PATH: &str = "/_htms";
+
+/// Number of hexadecimal digits of the content hash kept in asset file names.
+const HASH_LENGTH: usize = 16;
+
+/// The chunk runtime script.
+pub static HTMS_CHUNK_JS: LazyLock<Asset> = LazyLock::new(|| {
+    Asset::new(
+        "htms_chunk",
+        "js",
+        "text/javascript; charset=utf-8",
+        include_str!("static/htms_chunk.js"),
+    )
+});
+
+/// The stylesheet hiding empty placeholders.
+pub static STYLE_CSS: LazyLock<Asset> = LazyLock::new(|| {
+    Asset::new(
+        "style",
+        "css",
+        "text/css; charset=utf-8",
+        include_str!("static/style.css"),
+    )
+});
+
+/// How runtime assets are emitted into compiled templates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AssetMode {
+    /// Inline the runtime into a `<script>` and a `<style>` element (default).
+    #[default]
+    Inline,
+    /// Reference the runtime with `<script src>` and `<link rel="stylesheet">` elements.
+    External,
+}
+
+impl FromStr for AssetMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "inline" => Ok(Self::Inline),
+            "external" => Ok(Self::External),
+            other => Err(format!(
+                r#"unknown asset mode '{other}', expected "inline" or "external""#
+            )),
+        }
+    }
+}
+
+/// A static runtime file with its content hash and integrity digest.
+#[derive(Debug)]
+pub struct Asset {
+    file_name: String,
+    content_type: &'static str,
+    contents: &'static str,
+    integrity: String,
+}
+
+impl Asset {
+    fn new(
+        stem: &str,
+        extension: &str,
+        content_type: &'static str,
+        contents: &'static str,
+    ) -> Self {
+        let digest = Sha384::digest(contents.as_bytes());
+        let hash = digest
+            .iter()
+            .take(HASH_LENGTH / 2)
+            .fold(String::new(), |mut hash, byte| {
+                let _ = write!(hash, "{byte:02x}");
+                hash
+            });
+
+        Self {
+            file_name: format!("{stem}.{hash}.{extension}"),
+            content_type,
+            contents,
+            integrity: format!("sha384-{}", STANDARD.encode(digest)),
+        }
+    }
+
+    /// File name including the content hash, e.g. `htms_chunk.0123456789abcdef.js`.
+    #[inline]
+    #[must_use]
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    /// URL path the asset is served from, e.g. `/_htms/htms_chunk.0123456789abcdef.js`.
+    #[must_use]
+    pub fn path(&self) -> String {
+        format!("{ASSETS_PATH}/{}", self.file_name)
+    }
+
+    /// Value of the `Content-Type` header.
+    #[inline]
+    #[must_use]
+    pub const fn content_type(&self) -> &'static str {
+        self.content_type
+    }
+
+    /// Raw file contents.
+    #[inline]
+    #[must_use]
+    pub const fn contents(&self) -> &'static str {
+        self.contents
+    }
+
+    /// Subresource Integrity value, e.g. `sha384-...`.
+    #[inline]
+    #[must_use]
+    pub fn integrity(&self) -> &str {
+        &self.integrity
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[allow(clippy::expect_used)]
+mod tests {
+    use super::{ASSETS_PATH, AssetMode, HTMS_CHUNK_JS, STYLE_CSS};
+
+    #[test]
+    fn file_name_embeds_content_hash() {
+        let file_name = HTMS_CHUNK_JS.file_name();
+        let hash = file_name
+            .strip_prefix("htms_chunk.")
+            .and_then(|rest| rest.strip_suffix(".js"))
+            .expect("hashed file name");
+
+        assert_eq!(hash.len(), 16);
+        assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(HTMS_CHUNK_JS.file_name(), STYLE_CSS.file_name());
+    }
+
+    #[test]
+    fn path_is_served_under_assets_path() {
+        assert_eq!(
+            STYLE_CSS.path(),
+            format!("{ASSETS_PATH}/{}", STYLE_CSS.file_name())
+        );
+    }
+
+    #[test]
+    fn integrity_is_a_sha384_digest() {
+        let integrity = STYLE_CSS.integrity();
+        let digest = integrity.strip_prefix("sha384-").expect("sha384 prefix");
+
+        // 48 bytes of digest encoded in base64
+        assert_eq!(digest.len(), 64);
+    }
+
+    #[test]
+    fn asset_mode_from_str() {
+        assert_eq!("inline".parse(), Ok(AssetMode::Inline));
+        assert_eq!("external".parse(), Ok(AssetMode::External));
+        assert!("cdn".parse::<AssetMode>().is_err());
+    }
+}
@@ -1,18 +1,31 @@
 //! Adapter for integrating **htms** with [axum](https://github.com/tokio-rs/axum).
 //!
-//! This module provides [`HtmlStream`] which lets you stream HTML chunks into an Axum response.
+//! This module provides [`HtmlStream`] which lets you stream HTML chunks into an Axum response,
+//! an [`IntoResponse`] implementation for [`Rendered`] which applies the status and headers set by
+//! critical tasks, and [`assets_router`] which serves the external chunk runtime.
 
 use std::convert::Infallible;
 
 use axum::{
-    BoxError,
+    BoxError, Router,
     body::{Body, Bytes},
-    http::header::{CONTENT_TYPE, TRANSFER_ENCODING},
+    http::{
+        HeaderName, HeaderValue, StatusCode,
+        header::{CACHE_CONTROL, CONTENT_TYPE, TRANSFER_ENCODING},
+    },
     response::{IntoResponse, Response},
+    routing::get,
 };
 use futures_core::{Stream, TryStream};
 use futures_util::{StreamExt, stream::Map};
 
+use crate::{
+    assets::{Asset, HTMS_CHUNK_JS, STYLE_CSS},
+    render::Rendered,
+};
+
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
 /// Stream HTML chunks as an Axum response.
 ///
 /// Wraps any [`Stream`] of [`Bytes`] into a valid chunked `text/html` response.
@@ -73,19 +86,116 @@ where
     }
 }
 
+/// Stream a [`Rendered`] template, with the status and headers set by its critical tasks.
+///
+/// An invalid status code becomes `500 Internal Server Error`, invalid headers are skipped.
+///
+/// # Example
+/// ```rust
+/// use axum::response::{IntoResponse, Response};
+/// use htms_core::{Bytes, Render, Task, task::Output};
+///
+/// struct Product;
+///
+/// impl Render for Product {
+///     fn template() -> Bytes {
+///         "<main><!--htms:await:product--></main>".into()
+///     }
+///
+///     fn tasks(self) -> Option<Vec<Task>> {
+///         Some(vec![Task::critical("product", async {
+///             Output::new("<h1>Not found</h1>").with_status(404)
+///         })])
+///     }
+/// }
+///
+/// async fn handler() -> Response {
+///     Product.render_with_parts().await.into_response()
+/// }
+/// ```
+impl IntoResponse for Rendered {
+    fn into_response(self) -> Response {
+        let mut response = HtmlStream::from(self.stream).into_response();
+
+        if let Some(status) = self.status {
+            *response.status_mut() =
+                StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+
+        for (name, value) in self.headers {
+            if let (Ok(name), Ok(value)) =
+                (HeaderName::try_from(name), HeaderValue::try_from(value))
+            {
+                response.headers_mut().append(name, value);
+            }
+        }
+
+        response
+    }
+}
+
+/// Serve the chunk runtime files referenced by templates built with
+/// [`AssetMode::External`](crate::assets::AssetMode::External).
+///
+/// The files are served under [`ASSETS_PATH`](crate::assets::ASSETS_PATH) with their hashed
+/// file names and immutable cache headers. Merge it into your application router.
+///
+/// # Example
+/// ```rust
+/// use axum::{Router, routing::get};
+/// use htms_core::axum::assets_router;
+///
+/// fn app() -> Router {
+///     Router::new()
+///         .route("/", get(|| async { "Hello" }))
+///         .merge(assets_router())
+/// }
+/// ```
+pub fn assets_router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    [&*HTMS_CHUNK_JS, &*STYLE_CSS].into_iter().fold(
+        Router::new(),
+        |router, asset: &'static Asset| {
+            router.route(&asset.path(), get(move || serve_asset(asset)))
+        },
+    )
+}
+
+#[allow(clippy::unused_async)]
+async fn serve_asset(asset: &'static Asset) -> Response {
+    (
+        [
+            (CONTENT_TYPE, asset.content_type()),
+            (CACHE_CONTROL, IMMUTABLE_CACHE_CONTROL),
+        ],
+        asset.contents(),
+    )
+        .into_response()
+}
+
 #[cfg(test)]
 #[cfg_attr(coverage_nightly, coverage(off))]
 #[allow(clippy::expect_used)]
 mod tests {
     use axum::{
         body::{Bytes, to_bytes},
-        http::header::{CONTENT_TYPE, TRANSFER_ENCODING},
+        http::{
+            Request, StatusCode,
+            header::{CACHE_CONTROL, CONTENT_TYPE, TRANSFER_ENCODING},
+        },
         response::IntoResponse,
     };
-    use futures_util::stream;
+    use futures_util::{StreamExt, stream};
     use http::HeaderValue;
+    use tower::ServiceExt;
 
-    use super::HtmlStream;
+    use super::{HtmlStream, assets_router};
+    use crate::{
+        assets::{HTMS_CHUNK_JS, STYLE_CSS},
+        render::Rendered,
+    };
 
     fn hello_world_chunks() -> Vec<Result<Bytes, axum::BoxError>> {
         vec![
@@ -145,4 +255,90 @@ mod tests {
 
         assert_eq!(&bytes[..], b"HelloWorld");
     }
+
+    #[tokio::test]
+    async fn assets_router_serves_runtime_with_immutable_cache() {
+        for asset in [&*HTMS_CHUNK_JS, &*STYLE_CSS] {
+            let request = Request::get(asset.path())
+                .body(axum::body::Body::empty())
+                .expect("build request");
+            let response = assets_router::<()>()
+                .oneshot(request)
+                .await
+                .expect("oneshot");
+            let headers = response.headers().clone();
+            let body = to_bytes(response.into_body(), usize::MAX)
+                .await
+                .expect("to_bytes failed");
+
+            assert_eq!(
+                headers.get(CONTENT_TYPE),
+                Some(&HeaderValue::from_static(asset.content_type()))
+            );
+            assert_eq!(
+                headers.get(CACHE_CONTROL),
+                Some(&HeaderValue::from_static(
+                    "public, max-age=31536000, immutable"
+                ))
+            );
+            assert_eq!(&body[..], asset.contents().as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn assets_router_ignores_unknown_files() {
+        let request = Request::get("/_htms/htms_chunk.js")
+            .body(axum::body::Body::empty())
+            .expect("build request");
+        let response = assets_router::<()>()
+            .oneshot(request)
+            .await
+            .expect("oneshot");
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn rendered_applies_status_and_headers() {
+        let rendered = Rendered {
+            status: Some(404),
+            headers: vec![
+                ("cache-control".into(), "no-store".into()),
+                ("invalid header".into(), "skipped".into()),
+            ],
+            stream: stream::iter([Bytes::from_static(b"<h1>Not found</h1>")]).boxed(),
+        };
+        let response = rendered.into_response();
+        let headers = response.headers().clone();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("to_bytes failed");
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            headers.get(CACHE_CONTROL),
+            Some(&HeaderValue::from_static("no-store"))
+        );
+        assert_eq!(
+            headers.get(CONTENT_TYPE),
+            Some(&HeaderValue::from_static("text/html; charset=utf-8"))
+        );
+        assert_eq!(headers.len(), 3);
+        assert_eq!(&body[..], b"<h1>Not found</h1>");
+    }
+
+    #[tokio::test]
+    async fn rendered_with_invalid_status_is_an_internal_error() {
+        let rendered = Rendered {
+            status: Some(42),
+            headers: Vec::new(),
+            stream: stream::empty().boxed(),
+        };
+
+        assert_eq!(
+            rendered.into_response().status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
 }
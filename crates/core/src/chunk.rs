@@ -0,0 +1,310 @@
+//! Chunk options for **htms**.
+//!
+//! Describes how the output of a [`Task`](crate::Task) lands in the page once its
+//! `<htms-chunk>` reaches the browser.
+//!
+//! # Example
+//! ```rust
+//! use htms_core::chunk::{ChunkOptions, Swap};
+//!
+//! let options = ChunkOptions {
+//!     swap: Swap::Append,
+//!     selector: Some(".metric".into()),
+//!     ..ChunkOptions::default()
+//! };
+//!
+//! assert_eq!(options.attributes(), r#" swap="append" selector=".metric""#);
+//! ```
+
+use std::{
+    fmt::{self, Write},
+    str::FromStr,
+};
+
+/// Where the chunk content is inserted, relative to its placeholder.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Swap {
+    /// Replace the whole placeholder element (default).
+    #[default]
+    Replace,
+    /// Replace the placeholder content, keeping the placeholder element.
+    Inner,
+    /// Insert after the last child of the placeholder.
+    Append,
+    /// Insert before the first child of the placeholder.
+    Prepend,
+    /// Insert before the placeholder element.
+    Before,
+    /// Insert after the placeholder element.
+    After,
+}
+
+impl Swap {
+    /// Name of the mode, as written in `data-htms-swap` and `<htms-chunk swap>`.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Replace => "replace",
+            Self::Inner => "inner",
+            Self::Append => "append",
+            Self::Prepend => "prepend",
+            Self::Before => "before",
+            Self::After => "after",
+        }
+    }
+}
+
+impl fmt::Display for Swap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Swap {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "replace" => Ok(Self::Replace),
+            "inner" => Ok(Self::Inner),
+            "append" => Ok(Self::Append),
+            "prepend" => Ok(Self::Prepend),
+            "before" => Ok(Self::Before),
+            "after" => Ok(Self::After),
+            other => Err(format!(
+                "unknown swap mode '{other}', expected one of: replace, inner, append, prepend, before, after"
+            )),
+        }
+    }
+}
+
+/// What a chunk updates in the page.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ChunkKind {
+    /// Insert the task output as HTML content, streamed as `<htms-chunk>` (default).
+    #[default]
+    Content,
+    /// Set the named attribute of the target element to the task output, streamed as `<htms-attr>`.
+    Attribute(String),
+    /// Apply the `<title>`, named `<meta>`, `<link rel="canonical">` and JSON-LD elements of the
+    /// task output to `document.head`, streamed as `<htms-head>`.
+    Head,
+}
+
+/// Options carried by a task chunk.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkOptions {
+    /// What the chunk updates, see [`ChunkKind`].
+    pub kind: ChunkKind,
+    /// Insertion mode, see [`Swap`].
+    pub swap: Swap,
+    /// CSS selector of the child node of the placeholder the content lands in.
+    ///
+    /// When `None` the placeholder itself is the target.
+    pub selector: Option<String>,
+    /// Execute the `<script>` elements of the swapped content.
+    ///
+    /// Scripts are inert otherwise, except data blocks such as JSON-LD.
+    pub scripts: bool,
+    /// Id of the chunk whose content holds the target of this one.
+    ///
+    /// The runtime waits for the parent chunk to be swapped before looking up the target.
+    pub parent: Option<String>,
+    /// Message announced to screen readers once the chunk is swapped.
+    pub announce: Option<String>,
+    /// `id` of the element the chunk targets, instead of its `data-htms` placeholder.
+    pub element_id: Option<String>,
+    /// Task method of the chunk, when the chunk id is not the method name, e.g. `user_card` for
+    /// the chunk `user_card-0` of `fn:user_card(42)` or `Card-0-news` of a namespaced `fn:news`.
+    pub task: Option<String>,
+}
+
+impl ChunkOptions {
+    /// Render the options as chunk element attributes, each with a leading space.
+    ///
+    /// Default values are omitted.
+    #[must_use]
+    pub fn attributes(&self) -> String {
+        let mut attributes = String::new();
+
+        if self.swap != Swap::default() {
+            let _ = write!(attributes, r#" swap="{}""#, self.swap);
+        }
+
+        if let Some(selector) = &self.selector {
+            let _ = write!(attributes, r#" selector="{}""#, escape_attribute(selector));
+        }
+
+        if self.scripts {
+            attributes.push_str(" scripts");
+        }
+
+        if let Some(parent) = &self.parent {
+            let _ = write!(attributes, r#" parent="{}""#, escape_attribute(parent));
+        }
+
+        if let Some(announce) = &self.announce {
+            let _ = write!(attributes, r#" announce="{}""#, escape_attribute(announce));
+        }
+
+        if let Some(element_id) = &self.element_id {
+            let _ = write!(
+                attributes,
+                r#" element-id="{}""#,
+                escape_attribute(element_id)
+            );
+        }
+
+        if let Some(task) = &self.task {
+            let _ = write!(attributes, r#" task="{}""#, escape_attribute(task));
+        }
+
+        attributes
+    }
+}
+
+/// Escape a value for use inside a double-quoted HTML attribute.
+#[must_use]
+pub fn escape_attribute(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::{ChunkKind, ChunkOptions, Swap, escape_attribute};
+
+    #[test]
+    fn swap_round_trips_through_str() {
+        for swap in [
+            Swap::Replace,
+            Swap::Inner,
+            Swap::Append,
+            Swap::Prepend,
+            Swap::Before,
+            Swap::After,
+        ] {
+            assert_eq!(swap.as_str().parse(), Ok(swap));
+        }
+    }
+
+    #[test]
+    fn swap_rejects_unknown_mode() {
+        let error = "outer".parse::<Swap>().unwrap_err();
+
+        assert!(error.contains("unknown swap mode 'outer'"));
+    }
+
+    #[test]
+    fn default_options_have_no_attributes() {
+        assert_eq!(ChunkOptions::default().attributes(), "");
+    }
+
+    #[test]
+    fn attributes_include_non_default_swap() {
+        let options = ChunkOptions {
+            swap: Swap::Prepend,
+            ..ChunkOptions::default()
+        };
+
+        assert_eq!(options.attributes(), r#" swap="prepend""#);
+    }
+
+    #[test]
+    fn attributes_include_escaped_selector() {
+        let options = ChunkOptions {
+            selector: Some(r#"[data-kind="metric"]"#.into()),
+            ..ChunkOptions::default()
+        };
+
+        assert_eq!(
+            options.attributes(),
+            r#" selector="[data-kind=&quot;metric&quot;]""#
+        );
+    }
+
+    #[test]
+    fn attributes_include_scripts_flag() {
+        let options = ChunkOptions {
+            swap: Swap::Inner,
+            scripts: true,
+            ..ChunkOptions::default()
+        };
+
+        assert_eq!(options.attributes(), r#" swap="inner" scripts"#);
+    }
+
+    #[test]
+    fn attributes_include_parent() {
+        let options = ChunkOptions {
+            parent: Some("comments".into()),
+            ..ChunkOptions::default()
+        };
+
+        assert_eq!(options.attributes(), r#" parent="comments""#);
+    }
+
+    #[test]
+    fn attributes_include_escaped_announce() {
+        let options = ChunkOptions {
+            announce: Some(r#"Sales "Q3" loaded"#.into()),
+            ..ChunkOptions::default()
+        };
+
+        assert_eq!(
+            options.attributes(),
+            r#" announce="Sales &quot;Q3&quot; loaded""#
+        );
+    }
+
+    #[test]
+    fn attributes_include_element_id() {
+        let options = ChunkOptions {
+            element_id: Some("latest-news".into()),
+            ..ChunkOptions::default()
+        };
+
+        assert_eq!(options.attributes(), r#" element-id="latest-news""#);
+    }
+
+    #[test]
+    fn attributes_include_task() {
+        let options = ChunkOptions {
+            task: Some("user_card".into()),
+            ..ChunkOptions::default()
+        };
+
+        assert_eq!(options.attributes(), r#" task="user_card""#);
+    }
+
+    #[test]
+    fn attributes_ignore_kind() {
+        let options = ChunkOptions {
+            kind: ChunkKind::Attribute("src".into()),
+            ..ChunkOptions::default()
+        };
+
+        assert_eq!(options.attributes(), "");
+    }
+
+    #[test]
+    fn escape_attribute_escapes_markup_characters() {
+        assert_eq!(
+            escape_attribute(r#"<a href="?a=1&b=2">"#),
+            "&lt;a href=&quot;?a=1&amp;b=2&quot;&gt;"
+        );
+    }
+}
@@ -6,7 +6,11 @@
 #[cfg(feature = "axum")]
 pub mod axum;
 
+pub mod assets;
+pub mod chunk;
 pub mod render;
+pub mod scope;
+pub mod segment;
 pub mod task;
 pub mod template;
 
@@ -63,12 +63,25 @@
 //! }
 //! ```
 
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
 use async_stream::stream;
 use bytes::Bytes;
 use futures_core::Stream;
-use futures_util::{FutureExt, StreamExt, stream::FuturesUnordered};
+use futures_util::{
+    FutureExt, StreamExt,
+    future::join_all,
+    stream::{self, BoxStream, FuturesUnordered},
+};
 
-use crate::task::Task;
+use crate::{
+    chunk::{ChunkKind, ChunkOptions, escape_attribute},
+    segment::{Instance, Item, Segments, interpolate, split, write},
+    task::{CRITICAL_MARKER_END, CRITICAL_MARKER_START, Task},
+};
 
 /// Trait defining rendering logic.
 ///
@@ -78,6 +91,78 @@ pub trait Render: Sized {
     /// Return the static HTML template for this renderer.
     fn template() -> Bytes;
 
+    /// Return the part of the document sent before the critical tasks are awaited.
+    ///
+    /// Compiled templates split at `</head>`, so stylesheets and preloads are fetched while the
+    /// server waits; [`template`](Render::template) is then the rest of the document.
+    ///
+    /// Defaults to `None`.
+    #[must_use]
+    fn head() -> Option<Bytes> {
+        None
+    }
+
+    /// Return the compiled [`Segments`] of the template.
+    ///
+    /// When set, the head and the template of each render instance are written from the segments
+    /// with its [`namespace`](Render::namespace), [`bindings`](Render::bindings),
+    /// [`conditions`](Render::conditions) and [`collections`](Render::collections), instead of
+    /// searching the markers of [`head`](Render::head) and [`template`](Render::template).
+    ///
+    /// Defaults to `None`.
+    #[must_use]
+    fn segments() -> Option<Segments> {
+        None
+    }
+
+    /// Return the prefix of the chunk ids of this render instance.
+    ///
+    /// Templates built with the `namespace` option hold a [`NAMESPACE_MARKER`] before each id,
+    /// replaced by this prefix, which is also prepended to the [`Task`] ids and parents, so the
+    /// same template can appear several times in a page.
+    ///
+    /// Defaults to `None`.
+    #[must_use]
+    fn namespace(&self) -> Option<String> {
+        None
+    }
+
+    /// Return the values of the context bindings of this render instance, by binding path.
+    ///
+    /// Compiled templates hold a [`binding_marker`] for each `data-htms="text:..."` and
+    /// `data-htms-attr-<name>="..."` binding, replaced by the escaped value in the head and the
+    /// template.
+    ///
+    /// Defaults to no bindings.
+    #[must_use]
+    fn bindings(&self) -> Vec<(&'static str, String)> {
+        Vec::new()
+    }
+
+    /// Return the conditions of this render instance, by condition path.
+    ///
+    /// Compiled templates hold a [`section_marker`] around each `data-htms="if:..."` and
+    /// `data-htms="unless:..."` element, written or omitted from the head and the template
+    /// depending on the condition. Missing conditions do not hold.
+    ///
+    /// Defaults to no conditions.
+    #[must_use]
+    fn conditions(&self) -> Vec<(&'static str, bool)> {
+        Vec::new()
+    }
+
+    /// Return the items of the collections of this render instance, by collection path.
+    ///
+    /// Compiled templates hold an [`each_marker`] around each `data-htms="each:..."` element,
+    /// written once per [`Item`] with its own bindings and conditions. Missing collections are
+    /// empty.
+    ///
+    /// Defaults to no collections.
+    #[must_use]
+    fn collections(&self) -> Vec<(&'static str, Vec<Item>)> {
+        Vec::new()
+    }
+
     /// Return the list of tasks associated with this renderer.
     ///
     /// Defaults to `None`.
@@ -85,10 +170,31 @@ pub trait Render: Sized {
         None
     }
 
-    /// Build a `<htms-chunk>` wrapper from an ID and HTML fragment.
+    /// Build a chunk from an ID, the task [`ChunkOptions`] and the task output.
+    ///
+    /// Content chunks wrap the HTML fragment in a `<htms-chunk>`, attribute chunks carry the
+    /// escaped value in a `<htms-attr>` and head chunks keep their elements inert in the
+    /// `<template>` of a `<htms-head>`.
     #[must_use]
-    fn response(id: &str, html: &str) -> Bytes {
-        format!(r#"<htms-chunk target="{id}">{html}</htms-chunk>{}"#, "\n").into()
+    fn response(id: &str, options: &ChunkOptions, html: &str) -> Bytes {
+        let attributes = options.attributes();
+
+        match &options.kind {
+            ChunkKind::Content => format!(
+                r#"<htms-chunk target="{id}"{attributes}>{html}</htms-chunk>{}"#,
+                "\n"
+            ),
+            ChunkKind::Attribute(name) => format!(
+                r#"<htms-attr target="{id}" name="{name}" value="{}"{attributes}></htms-attr>{}"#,
+                escape_attribute(html),
+                "\n"
+            ),
+            ChunkKind::Head => format!(
+                r#"<htms-head target="{id}"{attributes}><template>{html}</template></htms-head>{}"#,
+                "\n"
+            ),
+        }
+        .into()
     }
 
     /// Optionally, return a final chunk to yield after all tasks complete.
@@ -99,33 +205,253 @@ pub trait Render: Sized {
     }
 
     /// Render the template plus task results as a stream of HTML chunks.
+    ///
+    /// The [`head`](Render::head) is yielded right away, then critical tasks are awaited and
+    /// inlined before the template is yielded, the other tasks stream as they complete. The head
+    /// is already sent when they are awaited, so critical head tasks stream as head chunks.
     #[must_use]
     fn render(self) -> impl Stream<Item = Bytes> {
-        stream! {
-            yield Self::template();
+        let namespace = self.namespace();
+        let (head, template) = instance_template(&self, namespace.as_deref());
+        let (head, tasks) = stream_head_tasks(head, self.tasks().unwrap_or_default());
+        let rendered = render_tasks::<Self>(tasks, None, template, namespace);
 
-            if let Some(tasks) = self.tasks() {
-                let mut tasks_unordered = FuturesUnordered::new();
+        stream::iter(head).chain(rendered.map(|rendered| rendered.stream).flatten_stream())
+    }
 
-                for task in tasks {
-                    let id = task.id;
-                    let future = task.future.map(move |output| Self::response(&id, &output));
+    /// Await the critical tasks, then return the response metadata they set with the stream.
+    ///
+    /// The status is taken from the first critical task (in task order) that sets one, the
+    /// headers of every critical task are kept. The status must be known before the first byte,
+    /// so unlike [`render`](Render::render) the head is only sent once the critical tasks are done,
+    /// with the outputs of the critical head tasks inlined into it.
+    fn render_with_parts(self) -> impl Future<Output = Rendered> + Send {
+        let namespace = self.namespace();
+        let (head, template) = instance_template(&self, namespace.as_deref());
 
-                    tasks_unordered.push(future.boxed());
-                }
+        render_tasks::<Self>(self.tasks().unwrap_or_default(), head, template, namespace)
+    }
+}
+
+/// The head and the template of a render instance, with its namespace and context values.
+fn instance_template<R: Render>(render: &R, namespace: Option<&str>) -> (Option<Bytes>, Bytes) {
+    let bindings = render.bindings();
+    let conditions = render.conditions();
+    let collections = render.collections();
+    let instance = Instance {
+        namespace,
+        bindings: &bindings,
+        conditions: &conditions,
+        collections: &collections,
+    };
+
+    R::segments().map_or_else(
+        || {
+            (
+                R::head().map(|head| apply_markers(head, &instance)),
+                apply_markers(R::template(), &instance),
+            )
+        },
+        |segments| {
+            (
+                segments.head.map(|head| interpolate(head, &instance)),
+                interpolate(segments.template, &instance),
+            )
+        },
+    )
+}
+
+/// Turn the critical head tasks into streamed ones for a head sent before they are awaited, their
+/// markers are removed from the head.
+fn stream_head_tasks(head: Option<Bytes>, tasks: Vec<Task>) -> (Option<Bytes>, Vec<Task>) {
+    let (head_tasks, mut tasks): (Vec<Task>, Vec<Task>) = tasks
+        .into_iter()
+        .partition(|task| task.is_critical() && task.options().kind == ChunkKind::Head);
+
+    if head_tasks.is_empty() {
+        return (head, tasks);
+    }
+
+    let removed = head_tasks
+        .iter()
+        .map(|task| (task.id.as_bytes(), String::new()))
+        .collect::<HashMap<_, _>>();
+    let head = head.map(|head| inline_critical_outputs(&head, &removed));
+
+    tasks.extend(head_tasks.into_iter().map(Task::streamed));
 
-                while let Some(bytes) = tasks_unordered.next().await {
-                    yield bytes;
+    (head, tasks)
+}
+
+/// Await the critical tasks, then build the stream of the head, the template and the chunks.
+fn render_tasks<R: Render>(
+    tasks: Vec<Task>,
+    mut head: Option<Bytes>,
+    mut template: Bytes,
+    namespace: Option<String>,
+) -> impl Future<Output = Rendered> + Send {
+    let (critical, streamed): (Vec<Task>, Vec<Task>) =
+        tasks.into_iter().partition(Task::is_critical);
+
+    async move {
+        let mut status = None;
+        let mut headers = Vec::new();
+
+        if !critical.is_empty() {
+            let ids = critical
+                .iter()
+                .map(|task| task.id.clone())
+                .collect::<Vec<_>>();
+            let outputs = join_all(critical.into_iter().map(|task| task.future)).await;
+            let mut html = HashMap::with_capacity(ids.len());
+
+            for (id, output) in ids.iter().zip(outputs) {
+                status = status.or(output.status);
+                headers.extend(output.headers);
+                html.insert(id.as_bytes(), output.html);
+            }
+
+            head = head.map(|head| inline_critical_outputs(&head, &html));
+            template = inline_critical_outputs(&template, &html);
+        }
+
+        let stream = stream! {
+            if let Some(head) = head {
+                yield head;
+            }
+
+            yield template;
+
+            let mut tasks_unordered = FuturesUnordered::new();
+
+            for task in streamed {
+                let (mut id, future, mut options) = task.into_parts();
+
+                if let Some(namespace) = &namespace {
+                    options.task = options.task.or_else(|| Some(id.clone()));
+                    id.insert_str(0, namespace);
+                    options.parent = options.parent.map(|parent| format!("{namespace}{parent}"));
+                    options.element_id = options.element_id.map(|id| format!("{namespace}{id}"));
                 }
+
+                let future = future.map(move |output| R::response(&id, &options, &output.html));
+
+                tasks_unordered.push(future.boxed());
+            }
+
+            while let Some(bytes) = tasks_unordered.next().await {
+                yield bytes;
             }
 
-            if let Some(chunk) = Self::final_chunk() {
+            if let Some(chunk) = R::final_chunk() {
                 yield chunk;
             }
+        };
+
+        Rendered {
+            status,
+            headers,
+            stream: stream.boxed(),
         }
     }
 }
 
+/// Replace the critical markers of `template` with the outputs of their tasks, in a single pass.
+///
+/// Markers of unknown ids are kept, and the template bytes are copied as they are.
+fn inline_critical_outputs(template: &[u8], outputs: &HashMap<&[u8], String>) -> Bytes {
+    let length = outputs.values().map(String::len).sum::<usize>();
+    let mut html = Vec::with_capacity(template.len() + length);
+    let mut rest = template;
+
+    while let Some(start) = find_bytes(rest, CRITICAL_MARKER_START.as_bytes()) {
+        let id_start = start + CRITICAL_MARKER_START.len();
+        let Some(id_length) = find_bytes(&rest[id_start..], CRITICAL_MARKER_END.as_bytes()) else {
+            break;
+        };
+        let id_end = id_start + id_length;
+
+        if let Some(output) = outputs.get(&rest[id_start..id_end]) {
+            html.extend_from_slice(&rest[..start]);
+            html.extend_from_slice(output.as_bytes());
+            rest = &rest[id_end + CRITICAL_MARKER_END.len()..];
+        } else {
+            html.extend_from_slice(&rest[..id_start]);
+            rest = &rest[id_start..];
+        }
+    }
+
+    html.extend_from_slice(rest);
+    html.into()
+}
+
+/// Position of the first occurrence of `needle` in `haystack`.
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Start of every placeholder of the compiled templates, reserved in the template sources.
+pub const MARKER_PREFIX: &str = "%htms:";
+
+/// Placeholder of the instance prefix in the ids of templates built with the `namespace` option.
+pub const NAMESPACE_MARKER: &str = "%htms:ns%";
+
+/// Return a prefix unique to this process for a new render instance of `name`, e.g. `card-7-`.
+#[must_use]
+pub fn unique_namespace(name: &str) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    format!("{name}-{}-", COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Placeholder left in compiled templates for the value of the context binding `path`.
+#[must_use]
+pub fn binding_marker(path: &str) -> String {
+    format!("%htms:bind:{path}%")
+}
+
+/// Placeholder opening a conditional section of a compiled template, closed by
+/// [`END_SECTION_MARKER`]; `negated` for `unless:` sections.
+#[must_use]
+pub fn section_marker(path: &str, negated: bool) -> String {
+    if negated {
+        format!("%htms:unless:{path}%")
+    } else {
+        format!("%htms:if:{path}%")
+    }
+}
+
+/// Placeholder opening a section of a compiled template repeated for each item of the collection
+/// `path`, closed by [`END_SECTION_MARKER`].
+#[must_use]
+pub fn each_marker(path: &str) -> String {
+    format!("%htms:each:{path}%")
+}
+
+/// Placeholder closing the innermost conditional or repeated section of a compiled template.
+pub const END_SECTION_MARKER: &str = "%htms:end%";
+
+/// Write the markers of a template with the values of a render instance.
+fn apply_markers(template: Bytes, instance: &Instance<'_>) -> Bytes {
+    if find_bytes(&template, MARKER_PREFIX.as_bytes()).is_none() {
+        return template;
+    }
+
+    write(&split(&template), instance)
+}
+
+/// A render whose critical tasks are done: the response metadata and the HTML stream.
+pub struct Rendered {
+    /// Status code set by a critical task.
+    pub status: Option<u16>,
+    /// Headers set by the critical tasks.
+    pub headers: Vec<(String, String)>,
+    /// The template followed by the streamed chunks.
+    pub stream: BoxStream<'static, Bytes>,
+}
+
 #[cfg(test)]
 #[cfg_attr(coverage_nightly, coverage(off))]
 #[allow(clippy::expect_used)]
@@ -181,10 +507,17 @@ mod render_template_only {
 #[cfg_attr(coverage_nightly, coverage(off))]
 #[allow(clippy::expect_used)]
 mod render_template_with_tasks {
+    use std::future;
+
     use bytes::Bytes;
-    use futures_util::StreamExt;
+    use futures_util::{FutureExt, StreamExt};
 
-    use crate::{Render, Task};
+    use crate::{
+        Render, Task,
+        chunk::{ChunkKind, ChunkOptions, Swap},
+        render::unique_namespace,
+        task::Output,
+    };
 
     const TEMPLATE: &[u8; 33] = b"<html>template with tasks</html>\n";
     const FINAL_CHUNK: &[u8; 21] = b"<!-- final chunk -->\n";
@@ -233,8 +566,8 @@ mod render_template_with_tasks {
         let chunks: Vec<Bytes> = stream.collect().await;
         let responses: Vec<Bytes> = chunks[1..chunks.len()].to_vec();
         let expected_responses = [
-            Template::response("first_task", FIRST_TASK_OUTPUT),
-            Template::response("second_task", SECOND_TASK_OUTPUT),
+            Template::response("first_task", &ChunkOptions::default(), FIRST_TASK_OUTPUT),
+            Template::response("second_task", &ChunkOptions::default(), SECOND_TASK_OUTPUT),
         ];
 
         assert_eq!(chunks.len(), 3);
@@ -249,8 +582,8 @@ mod render_template_with_tasks {
         let chunks: Vec<Bytes> = stream.collect().await;
         let responses: Vec<Bytes> = chunks[1..chunks.len() - 1].to_vec();
         let expected_responses = [
-            Template::response("first_task", FIRST_TASK_OUTPUT),
-            Template::response("second_task", SECOND_TASK_OUTPUT),
+            Template::response("first_task", &ChunkOptions::default(), FIRST_TASK_OUTPUT),
+            Template::response("second_task", &ChunkOptions::default(), SECOND_TASK_OUTPUT),
         ];
 
         assert_eq!(chunks.len(), 4);
@@ -262,7 +595,11 @@ mod render_template_with_tasks {
 
     #[tokio::test]
     async fn response_returns_expected_format() {
-        let bytes = TemplateWithFinalChunk::response("identifier", "<h1>html payload</h1>");
+        let bytes = TemplateWithFinalChunk::response(
+            "identifier",
+            &ChunkOptions::default(),
+            "<h1>html payload</h1>",
+        );
         let expected = Bytes::from_static(
             br#"<htms-chunk target="identifier"><h1>html payload</h1></htms-chunk>
 "#,
@@ -270,4 +607,450 @@ mod render_template_with_tasks {
 
         assert_eq!(bytes, expected);
     }
+
+    #[tokio::test]
+    async fn response_carries_swap_mode() {
+        let options = ChunkOptions {
+            swap: Swap::Append,
+            ..ChunkOptions::default()
+        };
+        let bytes = TemplateWithFinalChunk::response("identifier", &options, "<li>item</li>");
+        let expected = Bytes::from_static(
+            br#"<htms-chunk target="identifier" swap="append"><li>item</li></htms-chunk>
+"#,
+        );
+
+        assert_eq!(bytes, expected);
+    }
+
+    #[tokio::test]
+    async fn response_patches_attribute_with_escaped_value() {
+        let options = ChunkOptions {
+            kind: ChunkKind::Attribute("href".into()),
+            ..ChunkOptions::default()
+        };
+        let bytes =
+            TemplateWithFinalChunk::response("profile_link", &options, r#"/users?id=1&tab="bio""#);
+        let expected = Bytes::from_static(
+            br#"<htms-attr target="profile_link" name="href" value="/users?id=1&amp;tab=&quot;bio&quot;"></htms-attr>
+"#,
+        );
+
+        assert_eq!(bytes, expected);
+    }
+
+    #[tokio::test]
+    async fn response_wraps_head_elements_in_template() {
+        let options = ChunkOptions {
+            kind: ChunkKind::Head,
+            ..ChunkOptions::default()
+        };
+        let bytes = TemplateWithFinalChunk::response(
+            "product_head",
+            &options,
+            r#"<title>Blue shoes</title><meta name="description" content="Comfy">"#,
+        );
+        let expected = Bytes::from_static(
+            br#"<htms-head target="product_head"><template><title>Blue shoes</title><meta name="description" content="Comfy"></template></htms-head>
+"#,
+        );
+
+        assert_eq!(bytes, expected);
+    }
+
+    #[tokio::test]
+    async fn render_uses_task_options() {
+        struct Appending;
+
+        impl Render for Appending {
+            fn template() -> Bytes {
+                Bytes::from_static(TEMPLATE)
+            }
+
+            fn tasks(self) -> Option<Vec<Task>> {
+                Some(vec![
+                    Task::new("list", async { "<li>item</li>".into() }).with_options(
+                        ChunkOptions {
+                            swap: Swap::Append,
+                            selector: Some(".items".into()),
+                            ..ChunkOptions::default()
+                        },
+                    ),
+                ])
+            }
+        }
+
+        let chunks: Vec<Bytes> = Appending.render().collect().await;
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(
+            chunks[1],
+            Bytes::from_static(
+                br#"<htms-chunk target="list" swap="append" selector=".items"><li>item</li></htms-chunk>
+"#
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn render_inlines_critical_tasks_before_streaming() {
+        struct Critical;
+
+        impl Render for Critical {
+            fn template() -> Bytes {
+                Bytes::from_static(b"<main><!--htms:await:auth--></main>")
+            }
+
+            fn tasks(self) -> Option<Vec<Task>> {
+                Some(vec![
+                    Task::new("news", async { "<p>news</p>".to_string() }),
+                    Task::critical("auth", async { "<p>Hi Alice</p>" }),
+                ])
+            }
+        }
+
+        let chunks: Vec<Bytes> = Critical.render().collect().await;
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(
+            chunks[0],
+            Bytes::from_static(b"<main><p>Hi Alice</p></main>")
+        );
+        assert_eq!(
+            chunks[1],
+            Critical::response("news", &ChunkOptions::default(), "<p>news</p>")
+        );
+    }
+
+    #[tokio::test]
+    async fn render_inlines_critical_tasks_in_non_utf8_template() {
+        struct Latin1;
+
+        impl Render for Latin1 {
+            fn template() -> Bytes {
+                Bytes::from_static(
+                    b"<p>caf\xe9</p><!--htms:await:auth--><!--htms:await:other--><!--htms:await:auth-->",
+                )
+            }
+
+            fn tasks(self) -> Option<Vec<Task>> {
+                Some(vec![Task::critical("auth", async { "<b>Hi</b>" })])
+            }
+        }
+
+        let chunks: Vec<Bytes> = Latin1.render().collect().await;
+
+        assert_eq!(
+            chunks,
+            [Bytes::from_static(
+                b"<p>caf\xe9</p><b>Hi</b><!--htms:await:other--><b>Hi</b>"
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn render_with_parts_collects_status_and_headers() {
+        struct NotFound;
+
+        impl Render for NotFound {
+            fn template() -> Bytes {
+                Bytes::from_static(b"<!--htms:await:product--><!--htms:await:session-->")
+            }
+
+            fn tasks(self) -> Option<Vec<Task>> {
+                Some(vec![
+                    Task::critical("product", async {
+                        Output::new("<h1>Not found</h1>")
+                            .with_status(404)
+                            .with_header("cache-control", "no-store")
+                    }),
+                    Task::critical("session", async {
+                        Output::new("")
+                            .with_status(200)
+                            .with_header("vary", "cookie")
+                    }),
+                ])
+            }
+        }
+
+        let rendered = NotFound.render_with_parts().await;
+        let chunks: Vec<Bytes> = rendered.stream.collect().await;
+
+        assert_eq!(rendered.status, Some(404));
+        assert_eq!(
+            rendered.headers,
+            [
+                ("cache-control".to_string(), "no-store".to_string()),
+                ("vary".to_string(), "cookie".to_string()),
+            ]
+        );
+        assert_eq!(chunks, [Bytes::from_static(b"<h1>Not found</h1>")]);
+    }
+
+    struct WithHead;
+
+    impl Render for WithHead {
+        fn head() -> Option<Bytes> {
+            Some(Bytes::from_static(b"<head></head>"))
+        }
+
+        fn template() -> Bytes {
+            Bytes::from_static(b"<body><!--htms:await:auth--></body>")
+        }
+
+        fn tasks(self) -> Option<Vec<Task>> {
+            Some(vec![Task::critical("auth", future::pending::<Output>())])
+        }
+    }
+
+    #[tokio::test]
+    async fn render_yields_head_before_critical_tasks() {
+        let mut stream = Box::pin(WithHead.render());
+
+        assert_eq!(
+            stream.next().await,
+            Some(Bytes::from_static(b"<head></head>"))
+        );
+        assert!(stream.next().now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn render_with_parts_sends_head_with_template() {
+        struct Ready;
+
+        impl Render for Ready {
+            fn head() -> Option<Bytes> {
+                Some(Bytes::from_static(b"<head></head>"))
+            }
+
+            fn template() -> Bytes {
+                Bytes::from_static(b"<body><!--htms:await:auth--></body>")
+            }
+
+            fn tasks(self) -> Option<Vec<Task>> {
+                Some(vec![Task::critical("auth", async { "<p>Hi</p>" })])
+            }
+        }
+
+        assert!(WithHead.render_with_parts().now_or_never().is_none());
+
+        let chunks: Vec<Bytes> = Ready.render_with_parts().await.stream.collect().await;
+
+        assert_eq!(
+            chunks,
+            [
+                Bytes::from_static(b"<head></head>"),
+                Bytes::from_static(b"<body><p>Hi</p></body>"),
+            ]
+        );
+    }
+
+    struct AwaitedHead;
+
+    impl Render for AwaitedHead {
+        fn head() -> Option<Bytes> {
+            Some(Bytes::from_static(b"<head><!--htms:await:seo--></head>"))
+        }
+
+        fn template() -> Bytes {
+            Bytes::from_static(b"<body></body>")
+        }
+
+        fn tasks(self) -> Option<Vec<Task>> {
+            Some(vec![
+                Task::critical("seo", async { "<title>Product</title>" }).with_options(
+                    ChunkOptions {
+                        kind: ChunkKind::Head,
+                        ..ChunkOptions::default()
+                    },
+                ),
+            ])
+        }
+    }
+
+    #[tokio::test]
+    async fn render_with_parts_inlines_critical_head_tasks_into_head() {
+        let chunks: Vec<Bytes> = AwaitedHead.render_with_parts().await.stream.collect().await;
+
+        assert_eq!(
+            chunks,
+            [
+                Bytes::from_static(b"<head><title>Product</title></head>"),
+                Bytes::from_static(b"<body></body>"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn render_streams_critical_head_tasks_after_early_head() {
+        let chunks: Vec<Bytes> = AwaitedHead.render().collect().await;
+
+        assert_eq!(
+            chunks,
+            [
+                Bytes::from_static(b"<head></head>"),
+                Bytes::from_static(b"<body></body>"),
+                Bytes::from_static(
+                    b"<htms-head target=\"seo\"><template><title>Product</title></template></htms-head>\n"
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn render_applies_namespace_to_template_and_tasks() {
+        struct Card(&'static str);
+
+        impl Render for Card {
+            fn template() -> Bytes {
+                Bytes::from_static(br#"<div data-htms="%htms:ns%stats"></div>"#)
+            }
+
+            fn namespace(&self) -> Option<String> {
+                Some(format!("{}-", self.0))
+            }
+
+            fn tasks(self) -> Option<Vec<Task>> {
+                Some(vec![
+                    Task::new("stats", async { "42".to_string() }).with_options(ChunkOptions {
+                        parent: Some("card".into()),
+                        ..ChunkOptions::default()
+                    }),
+                ])
+            }
+        }
+
+        let chunks: Vec<Bytes> = Card("first").render().collect().await;
+
+        assert_eq!(
+            chunks,
+            [
+                Bytes::from_static(br#"<div data-htms="first-stats"></div>"#),
+                Bytes::from_static(
+                    br#"<htms-chunk target="first-stats" parent="first-card" task="stats">42</htms-chunk>
+"#
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn render_namespaces_id_targets_per_instance() {
+        struct Card(&'static str);
+
+        impl Render for Card {
+            fn template() -> Bytes {
+                Bytes::from_static(br#"<div id="%htms:ns%stats"></div>"#)
+            }
+
+            fn namespace(&self) -> Option<String> {
+                Some(format!("{}-", self.0))
+            }
+
+            fn tasks(self) -> Option<Vec<Task>> {
+                Some(vec![
+                    Task::new("stats", async { "42".to_string() }).with_options(ChunkOptions {
+                        element_id: Some("stats".into()),
+                        ..ChunkOptions::default()
+                    }),
+                ])
+            }
+        }
+
+        let first: Vec<Bytes> = Card("first").render().collect().await;
+        let second: Vec<Bytes> = Card("second").render().collect().await;
+
+        assert_eq!(
+            first[0],
+            Bytes::from_static(br#"<div id="first-stats"></div>"#)
+        );
+        assert_eq!(
+            second[0],
+            Bytes::from_static(br#"<div id="second-stats"></div>"#)
+        );
+        assert!(String::from_utf8_lossy(&first[1]).contains(r#"element-id="first-stats""#));
+        assert!(String::from_utf8_lossy(&second[1]).contains(r#"element-id="second-stats""#));
+    }
+
+    #[tokio::test]
+    async fn render_strips_namespace_marker_without_namespace() {
+        struct Card;
+
+        impl Render for Card {
+            fn template() -> Bytes {
+                Bytes::from_static(br#"<div data-htms="%htms:ns%stats"></div>"#)
+            }
+        }
+
+        let chunks: Vec<Bytes> = Card.render().collect().await;
+
+        assert_eq!(
+            chunks,
+            [Bytes::from_static(br#"<div data-htms="stats"></div>"#)]
+        );
+    }
+
+    #[tokio::test]
+    async fn render_applies_escaped_bindings_to_head_and_template() {
+        struct Page(String);
+
+        impl Render for Page {
+            fn head() -> Option<Bytes> {
+                Some(Bytes::from_static(
+                    b"<head><title>%htms:bind:title%</title></head>",
+                ))
+            }
+
+            fn template() -> Bytes {
+                Bytes::from_static(br#"<a href="%htms:bind:link%">%htms:bind:title%</a>"#)
+            }
+
+            fn bindings(&self) -> Vec<(&'static str, String)> {
+                vec![("title", self.0.clone()), ("link", "/?a=1&b=2".into())]
+            }
+        }
+
+        let chunks: Vec<Bytes> = Page("Tom & <Jerry>".into()).render().collect().await;
+
+        assert_eq!(
+            chunks,
+            [
+                Bytes::from_static(b"<head><title>Tom &amp; &lt;Jerry&gt;</title></head>"),
+                Bytes::from_static(br#"<a href="/?a=1&amp;b=2">Tom &amp; &lt;Jerry&gt;</a>"#),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn render_writes_sections_by_condition() {
+        struct Page(bool);
+
+        impl Render for Page {
+            fn template() -> Bytes {
+                Bytes::from_static(
+                    b"%htms:if:admin%<nav>admin</nav>%htms:end%%htms:unless:admin%<p>guest</p>%htms:end%",
+                )
+            }
+
+            fn conditions(&self) -> Vec<(&'static str, bool)> {
+                vec![("admin", self.0)]
+            }
+        }
+
+        let admin: Vec<Bytes> = Page(true).render().collect().await;
+        let guest: Vec<Bytes> = Page(false).render().collect().await;
+
+        assert_eq!(admin, [Bytes::from_static(b"<nav>admin</nav>")]);
+        assert_eq!(guest, [Bytes::from_static(b"<p>guest</p>")]);
+    }
+
+    #[test]
+    fn unique_namespace_differs_per_call() {
+        let first = unique_namespace("card");
+        let second = unique_namespace("card");
+
+        assert!(first.starts_with("card-"));
+        assert!(first.ends_with('-'));
+        assert_ne!(first, second);
+    }
 }
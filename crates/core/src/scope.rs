@@ -0,0 +1,288 @@
+//! Scoped styles for **htms** fragments.
+//!
+//! A `<style data-htms-scoped>` in an included fragment only applies to that fragment: every
+//! selector gets a `[data-htms-scope-<id>]` attribute selector, stamped on the elements of the
+//! fragment by the template builder.
+//!
+//! # Example
+//! ```rust
+//! use htms_core::scope::scope_css;
+//!
+//! let css = scope_css(".title, p > b::before { color: red }", "data-htms-scope-0123abcd");
+//!
+//! assert_eq!(
+//!     css,
+//!     ".title[data-htms-scope-0123abcd], p > b[data-htms-scope-0123abcd]::before { color: red }"
+//! );
+//! ```
+
+use std::fmt::Write;
+
+use sha2::{Digest, Sha256};
+
+/// Prefix of the attribute stamped on the elements of a scoped fragment.
+pub const SCOPE_ATTRIBUTE_PREFIX: &str = "data-htms-scope-";
+
+/// Number of hexadecimal digits of the scope id.
+const SCOPE_ID_LENGTH: usize = 8;
+
+/// At-rules whose block holds style rules, scoped like the top-level ones.
+const NESTING_AT_RULES: [&str; 5] = ["@media", "@supports", "@container", "@layer", "@document"];
+
+/// Scope attribute of a fragment, derived from a stable `key` such as its path in the template root.
+#[must_use]
+pub fn scope_attribute(key: &str) -> String {
+    let digest = Sha256::digest(key.as_bytes());
+    let id = digest
+        .iter()
+        .take(SCOPE_ID_LENGTH / 2)
+        .fold(String::new(), |mut id, byte| {
+            let _ = write!(id, "{byte:02x}");
+            id
+        });
+
+    format!("{SCOPE_ATTRIBUTE_PREFIX}{id}")
+}
+
+/// Add a `[attribute]` selector to every selector of `css`.
+///
+/// The attribute selector goes at the end of each selector, before its pseudo-element if any.
+/// Rules inside `@media`, `@supports`, `@container`, `@layer` and `@document` are scoped too, other
+/// at-rules such as `@keyframes` or `@font-face` are left as is. Comments are removed.
+#[must_use]
+pub fn scope_css(css: &str, attribute: &str) -> String {
+    scope_rules(&strip_comments(css), &format!("[{attribute}]"))
+}
+
+fn scope_rules(css: &str, selector: &str) -> String {
+    let mut output = String::with_capacity(css.len());
+    let mut rest = css;
+
+    while let Some(open) = find_unquoted(rest, "{") {
+        let (statements, prelude) = rest[..open]
+            .rfind(';')
+            .map_or(("", &rest[..open]), |end| rest[..open].split_at(end + 1));
+        let close = matching_brace(rest, open);
+        let block = &rest[open + 1..close];
+
+        output.push_str(statements);
+
+        if prelude.trim_start().starts_with('@') {
+            output.push_str(prelude);
+            output.push('{');
+
+            if NESTING_AT_RULES
+                .iter()
+                .any(|at_rule| prelude.trim_start().starts_with(at_rule))
+            {
+                output.push_str(&scope_rules(block, selector));
+            } else {
+                output.push_str(block);
+            }
+        } else {
+            output.push_str(&scope_selectors(prelude, selector));
+            output.push('{');
+            output.push_str(block);
+        }
+
+        if close < rest.len() {
+            output.push('}');
+        }
+
+        rest = &rest[(close + 1).min(rest.len())..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+fn scope_selectors(prelude: &str, selector: &str) -> String {
+    split_top_level(prelude, ',')
+        .into_iter()
+        .map(|complex| {
+            let trimmed = complex.trim_end();
+            let (selector_end, trailing) = complex.split_at(trimmed.len());
+            let insert_at = pseudo_element_start(selector_end).unwrap_or(selector_end.len());
+
+            format!(
+                "{}{selector}{}{trailing}",
+                &selector_end[..insert_at],
+                &selector_end[insert_at..]
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Pseudo-elements written with a single colon, which CSS still accepts for compatibility.
+const LEGACY_PSEUDO_ELEMENTS: [&str; 4] = [":before", ":after", ":first-line", ":first-letter"];
+
+/// Byte index of the pseudo-element of a complex selector, `::name` or a legacy `:name`, the
+/// scope attribute must come before it.
+fn pseudo_element_start(selector: &str) -> Option<usize> {
+    let mut start = 0;
+
+    while let Some(index) = find_unquoted(&selector[start..], ":").map(|index| start + index) {
+        let rest = &selector[index..];
+
+        if rest.starts_with("::") {
+            return Some(index);
+        }
+
+        let legacy = LEGACY_PSEUDO_ELEMENTS.iter().find(|name| {
+            rest.get(..name.len())
+                .is_some_and(|prefix| prefix.eq_ignore_ascii_case(name))
+                && !rest[name.len()..]
+                    .starts_with(|c: char| c.is_alphanumeric() || c == '-' || c == '_')
+        });
+
+        if legacy.is_some() {
+            return Some(index);
+        }
+
+        start = index + 1;
+    }
+
+    None
+}
+
+/// Split `value` on `separator`, outside of parentheses, brackets and quotes.
+fn split_top_level(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut start = 0;
+
+    for (index, c) in value.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[') => depth += 1,
+            (None, ')' | ']') => depth = depth.saturating_sub(1),
+            (None, c) if c == separator && depth == 0 => {
+                parts.push(&value[start..index]);
+                start = index + c.len_utf8();
+            },
+            _ => {},
+        }
+    }
+
+    parts.push(&value[start..]);
+    parts
+}
+
+/// Byte index of the first `pattern` outside of quotes.
+fn find_unquoted(value: &str, pattern: &str) -> Option<usize> {
+    let mut quote = None;
+
+    value
+        .char_indices()
+        .find_map(|(index, c)| match (quote, c) {
+            (Some(q), c) if c == q => {
+                quote = None;
+                None
+            },
+            (Some(_), _) => None,
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                None
+            },
+            (None, _) => value[index..].starts_with(pattern).then_some(index),
+        })
+}
+
+/// Byte index of the `}` closing the `{` at `open`, or the length of `css` when it is not closed.
+fn matching_brace(css: &str, open: usize) -> usize {
+    let mut depth = 0usize;
+    let mut quote = None;
+
+    for (index, c) in css[open..].char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return open + index;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    css.len()
+}
+
+fn strip_comments(css: &str) -> String {
+    let mut output = String::with_capacity(css.len());
+    let mut rest = css;
+
+    while let Some(start) = rest.find("/*") {
+        output.push_str(&rest[..start]);
+        rest = rest[start + 2..]
+            .find("*/")
+            .map_or("", |end| &rest[start + 2 + end + 2..]);
+    }
+
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::{SCOPE_ATTRIBUTE_PREFIX, scope_attribute, scope_css};
+
+    #[test]
+    fn scope_attribute_is_stable_and_short() {
+        let attribute = scope_attribute("components/card.html");
+
+        assert_eq!(attribute, scope_attribute("components/card.html"));
+        assert_ne!(attribute, scope_attribute("components/chart.html"));
+        assert!(attribute.starts_with(SCOPE_ATTRIBUTE_PREFIX));
+        assert_eq!(attribute.len(), SCOPE_ATTRIBUTE_PREFIX.len() + 8);
+    }
+
+    #[test]
+    fn scope_every_selector_of_a_rule() {
+        assert_eq!(
+            scope_css(".a, div > .b:hover,\n.c:focus::after { color: red }", "s"),
+            ".a[s], div > .b:hover[s],\n.c:focus[s]::after { color: red }"
+        );
+    }
+
+    #[test]
+    fn scope_before_legacy_pseudo_elements() {
+        assert_eq!(
+            scope_css(
+                "b:before, p:hover:FIRST-LINE, a:first-letter, i:after-x, q[title=':after'] { x: y }",
+                "s"
+            ),
+            "b[s]:before, p:hover[s]:FIRST-LINE, a[s]:first-letter, i:after-x[s], q[title=':after'][s] { x: y }"
+        );
+    }
+
+    #[test]
+    fn scope_nested_rules_of_media_only() {
+        assert_eq!(
+            scope_css(
+                "@media (min-width: 1px) { .a { x: y } } @keyframes k { from { x: y } }",
+                "s"
+            ),
+            "@media (min-width: 1px) { .a[s] { x: y } } @keyframes k { from { x: y } }"
+        );
+    }
+
+    #[test]
+    fn keep_statements_strings_and_drop_comments() {
+        assert_eq!(
+            scope_css(
+                r#"@import url("a.css"); /* .x { } */ a[title="{,}"] { content: "}" }"#,
+                "s"
+            ),
+            r#"@import url("a.css");  a[title="{,}"][s] { content: "}" }"#
+        );
+    }
+}
@@ -0,0 +1,471 @@
+//! Compiled template segments for **htms**.
+//!
+//! A compiled template is split into static byte slices and holes: the per-instance namespace
+//! ([`NAMESPACE_MARKER`]), the context bindings ([`binding_marker`]) and the bounds of the
+//! conditional ([`section_marker`]) and repeated ([`each_marker`]) sections. The derive macro embeds the segments as
+//! `&'static [u8]` literals, so rendering an instance only writes the static parts and the escaped
+//! values, without searching the template for markers.
+//!
+//! # Example
+//! ```rust
+//! use htms_core::segment::{Instance, Segment, interpolate, split};
+//!
+//! let segments = split(br#"<h1 id="%htms:ns%title">%htms:bind:user.name%</h1>"#);
+//!
+//! assert_eq!(segments[1], Segment::Namespace);
+//! assert_eq!(segments[3], Segment::Binding("user.name"));
+//!
+//! const SEGMENTS: &[Segment<'static>] = &[
+//!     Segment::Static(b"<h1>"),
+//!     Segment::Binding("name"),
+//!     Segment::Static(b"</h1>"),
+//! ];
+//!
+//! let bindings = [("name", "Tom & Jerry".to_string())];
+//! let html = interpolate(SEGMENTS, &Instance { bindings: &bindings, ..Instance::default() });
+//!
+//! assert_eq!(html, "<h1>Tom &amp; Jerry</h1>");
+//! ```
+//!
+//! [`binding_marker`]: crate::render::binding_marker
+//! [`section_marker`]: crate::render::section_marker
+//! [`each_marker`]: crate::render::each_marker
+
+use bytes::{Bytes, BytesMut};
+
+use crate::{
+    chunk::escape_attribute,
+    render::{END_SECTION_MARKER, NAMESPACE_MARKER},
+};
+
+/// Start of the binding markers, followed by the binding path and a `%`.
+const BINDING_MARKER_PREFIX: &[u8] = b"%htms:bind:";
+
+/// Start of the `if:` section markers, followed by the condition path and a `%`.
+const IF_MARKER_PREFIX: &[u8] = b"%htms:if:";
+
+/// Start of the `unless:` section markers, followed by the condition path and a `%`.
+const UNLESS_MARKER_PREFIX: &[u8] = b"%htms:unless:";
+
+/// Start of the `each:` section markers, followed by the collection path and a `%`.
+const EACH_MARKER_PREFIX: &[u8] = b"%htms:each:";
+
+/// A part of a compiled template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment<'a> {
+    /// Bytes written as is.
+    Static(&'a [u8]),
+    /// The namespace of the render instance, nothing without a namespace.
+    Namespace,
+    /// The escaped value of a context binding, by path.
+    Binding(&'a str),
+    /// Start of a section written when the condition at this path holds.
+    If(&'a str),
+    /// Start of a section written when the condition at this path does not hold.
+    Unless(&'a str),
+    /// Start of a section written for each item of the collection at this path.
+    Each(&'a str),
+    /// End of the innermost section.
+    EndSection,
+}
+
+/// Values of a render instance written into the holes of the segments.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Instance<'a> {
+    /// Namespace of the render instance, see [`Render::namespace`](crate::Render::namespace).
+    pub namespace: Option<&'a str>,
+    /// Binding values by path, see [`Render::bindings`](crate::Render::bindings).
+    pub bindings: &'a [(&'a str, String)],
+    /// Conditions by path, see [`Render::conditions`](crate::Render::conditions).
+    pub conditions: &'a [(&'a str, bool)],
+    /// Items by collection path, see [`Render::collections`](crate::Render::collections).
+    pub collections: &'a [(&'a str, Vec<Item>)],
+}
+
+/// Values of an item of a collection, written into its `each:` section.
+///
+/// Paths start with the name of the item in the section, e.g. `item.name`; the other paths of the
+/// section are read from the [`Instance`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Item {
+    /// Binding values by path.
+    pub bindings: Vec<(&'static str, String)>,
+    /// Conditions by path.
+    pub conditions: Vec<(&'static str, bool)>,
+}
+
+/// A context value deciding whether an `if:` or `unless:` section is rendered.
+pub trait Condition {
+    /// Whether the condition holds: `true`, or `Some(_)`.
+    fn holds(&self) -> bool;
+}
+
+impl Condition for bool {
+    fn holds(&self) -> bool {
+        *self
+    }
+}
+
+impl<T> Condition for Option<T> {
+    fn holds(&self) -> bool {
+        self.is_some()
+    }
+}
+
+impl<T: Condition + ?Sized> Condition for &T {
+    fn holds(&self) -> bool {
+        (**self).holds()
+    }
+}
+
+/// Segments of a compiled template, see [`Render::segments`](crate::Render::segments).
+#[derive(Debug, Clone, Copy)]
+pub struct Segments {
+    /// The part of the document sent before the critical tasks are awaited.
+    pub head: Option<&'static [Segment<'static>]>,
+    /// The rest of the document.
+    pub template: &'static [Segment<'static>],
+}
+
+/// Split a compiled template on its namespace and binding markers.
+#[must_use]
+pub fn split(compiled: &[u8]) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut rest = compiled;
+
+    while let Some((start, end, hole)) = next_hole(rest) {
+        if start > 0 {
+            segments.push(Segment::Static(&rest[..start]));
+        }
+
+        segments.push(hole);
+        rest = &rest[end..];
+    }
+
+    if !rest.is_empty() {
+        segments.push(Segment::Static(rest));
+    }
+
+    segments
+}
+
+/// Write the segments with the values of a render instance.
+///
+/// Segments without holes are returned without copy. Bindings missing from the instance are empty
+/// and conditions missing from the instance do not hold.
+#[must_use]
+pub fn interpolate(segments: &'static [Segment<'static>], instance: &Instance<'_>) -> Bytes {
+    match segments {
+        [] => Bytes::new(),
+        [Segment::Static(bytes)] => Bytes::from_static(bytes),
+        _ => write(segments, instance),
+    }
+}
+
+/// Same as [`interpolate`], always copying the segments.
+#[must_use]
+pub fn write(segments: &[Segment<'_>], instance: &Instance<'_>) -> Bytes {
+    let mut html = BytesMut::with_capacity(
+        segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Static(bytes) => bytes.len(),
+                _ => 0,
+            })
+            .sum(),
+    );
+
+    write_section(segments, instance, None, &mut html);
+
+    html.freeze()
+}
+
+fn write_section(
+    segments: &[Segment<'_>],
+    instance: &Instance<'_>,
+    item: Option<&Item>,
+    html: &mut BytesMut,
+) {
+    // number of open sections being skipped, the outermost one did not hold
+    let mut skipped = 0usize;
+    let mut index = 0;
+
+    while let Some(segment) = segments.get(index) {
+        index += 1;
+
+        match segment {
+            Segment::If(_) | Segment::Unless(_) | Segment::Each(_) if skipped > 0 => skipped += 1,
+            Segment::EndSection if skipped > 0 => skipped -= 1,
+            _ if skipped > 0 => {},
+            Segment::If(path) => skipped = usize::from(!instance.holds(item, path)),
+            Segment::Unless(path) => skipped = usize::from(instance.holds(item, path)),
+            Segment::Each(path) => {
+                let end = index + section_length(&segments[index..]);
+
+                for item in instance.items(path) {
+                    write_section(&segments[index..end], instance, Some(item), html);
+                }
+
+                // past the end of the section
+                index = end + 1;
+            },
+            Segment::EndSection => {},
+            Segment::Static(bytes) => html.extend_from_slice(bytes),
+            Segment::Namespace => {
+                html.extend_from_slice(instance.namespace.unwrap_or_default().as_bytes());
+            },
+            Segment::Binding(path) => {
+                if let Some(value) = instance.binding(item, path) {
+                    html.extend_from_slice(escape_attribute(value).as_bytes());
+                }
+            },
+        }
+    }
+}
+
+/// Number of segments before the end of the section they are in.
+fn section_length(segments: &[Segment<'_>]) -> usize {
+    let mut depth = 0usize;
+
+    segments
+        .iter()
+        .position(|segment| match segment {
+            Segment::If(_) | Segment::Unless(_) | Segment::Each(_) => {
+                depth += 1;
+                false
+            },
+            Segment::EndSection if depth == 0 => true,
+            Segment::EndSection => {
+                depth -= 1;
+                false
+            },
+            _ => false,
+        })
+        .unwrap_or(segments.len())
+}
+
+impl Instance<'_> {
+    fn binding<'v>(&'v self, item: Option<&'v Item>, path: &str) -> Option<&'v String> {
+        item.into_iter()
+            .flat_map(|item| &item.bindings)
+            .chain(self.bindings)
+            .find_map(|(name, value)| (*name == path).then_some(value))
+    }
+
+    fn holds(&self, item: Option<&Item>, path: &str) -> bool {
+        item.into_iter()
+            .flat_map(|item| &item.conditions)
+            .chain(self.conditions)
+            .find_map(|(name, holds)| (*name == path).then_some(*holds))
+            .unwrap_or_default()
+    }
+
+    fn items(&self, path: &str) -> &[Item] {
+        self.collections
+            .iter()
+            .find_map(|(name, items)| (*name == path).then_some(items.as_slice()))
+            .unwrap_or_default()
+    }
+}
+
+/// Position and hole of the first marker of `bytes`.
+fn next_hole<'a>(bytes: &'a [u8]) -> Option<(usize, usize, Segment<'a>)> {
+    let namespace = NAMESPACE_MARKER.as_bytes();
+    let mut from = 0;
+
+    while let Some(offset) = bytes[from..].iter().position(|&byte| byte == b'%') {
+        let start = from + offset;
+        let marker = &bytes[start..];
+
+        if marker.starts_with(namespace) {
+            return Some((start, start + namespace.len(), Segment::Namespace));
+        }
+
+        if marker.starts_with(END_SECTION_MARKER.as_bytes()) {
+            return Some((start, start + END_SECTION_MARKER.len(), Segment::EndSection));
+        }
+
+        for (prefix, hole) in [
+            (
+                BINDING_MARKER_PREFIX,
+                Segment::Binding as fn(&'a str) -> Segment<'a>,
+            ),
+            (IF_MARKER_PREFIX, Segment::If),
+            (UNLESS_MARKER_PREFIX, Segment::Unless),
+            (EACH_MARKER_PREFIX, Segment::Each),
+        ] {
+            if let Some(path) = marker.strip_prefix(prefix)
+                && let Some(length) = path.iter().position(|&byte| byte == b'%')
+                && let Ok(path) = str::from_utf8(&path[..length])
+            {
+                return Some((start, start + prefix.len() + length + 1, hole(path)));
+            }
+        }
+
+        from = start + 1;
+    }
+
+    None
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{Condition, Instance, Item, Segment, interpolate, split};
+
+    #[test]
+    fn split_static_template() {
+        assert_eq!(split(b"<p>100%</p>"), [Segment::Static(b"<p>100%</p>")]);
+        assert!(split(b"").is_empty());
+    }
+
+    #[test]
+    fn split_on_namespace_and_binding_markers() {
+        assert_eq!(
+            split(b"%htms:ns%a<b>%htms:bind:user.name%</b>%htms:bind:x%"),
+            [
+                Segment::Namespace,
+                Segment::Static(b"a<b>"),
+                Segment::Binding("user.name"),
+                Segment::Static(b"</b>"),
+                Segment::Binding("x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_on_section_markers() {
+        assert_eq!(
+            split(b"%htms:if:user.admin%<a>%htms:end%%htms:unless:user%b%htms:end%%htms:each:x%%htms:end%"),
+            [
+                Segment::If("user.admin"),
+                Segment::Static(b"<a>"),
+                Segment::EndSection,
+                Segment::Unless("user"),
+                Segment::Static(b"b"),
+                Segment::EndSection,
+                Segment::Each("x"),
+                Segment::EndSection,
+            ]
+        );
+    }
+
+    #[test]
+    fn interpolate_sections_by_condition() {
+        const SEGMENTS: &[Segment<'static>] = &[
+            Segment::If("admin"),
+            Segment::Static(b"<admin>"),
+            Segment::If("missing"),
+            Segment::Static(b"<missing>"),
+            Segment::EndSection,
+            Segment::EndSection,
+            Segment::Unless("admin"),
+            Segment::Static(b"<guest>"),
+            Segment::If("admin"),
+            Segment::Static(b"<never>"),
+            Segment::EndSection,
+            Segment::EndSection,
+            Segment::Unless("missing"),
+            Segment::Static(b"<always>"),
+            Segment::EndSection,
+        ];
+
+        let conditions = [("admin", true)];
+        let admin = Instance {
+            conditions: &conditions,
+            ..Instance::default()
+        };
+
+        assert_eq!(interpolate(SEGMENTS, &admin), "<admin><always>");
+        assert_eq!(
+            interpolate(SEGMENTS, &Instance::default()),
+            "<guest><always>"
+        );
+    }
+
+    #[test]
+    fn interpolate_each_item_of_collections() {
+        const SEGMENTS: &[Segment<'static>] = &[
+            Segment::Static(b"<ul>"),
+            Segment::Each("links"),
+            Segment::Static(b"<li class=\""),
+            Segment::Binding("class"),
+            Segment::Static(b"\">"),
+            Segment::Binding("link.name"),
+            Segment::If("link.active"),
+            Segment::Static(b"*"),
+            Segment::EndSection,
+            Segment::Static(b"</li>"),
+            Segment::EndSection,
+            Segment::Each("missing"),
+            Segment::Static(b"<li>missing</li>"),
+            Segment::EndSection,
+            Segment::Static(b"</ul>"),
+        ];
+
+        let item = |name: &str, active| Item {
+            bindings: vec![("link.name", name.to_string())],
+            conditions: vec![("link.active", active)],
+        };
+        let bindings = [("class", "link".to_string())];
+        let collections = [("links", vec![item("Home", true), item("A & B", false)])];
+        let instance = Instance {
+            bindings: &bindings,
+            collections: &collections,
+            ..Instance::default()
+        };
+
+        assert_eq!(
+            interpolate(SEGMENTS, &instance),
+            r#"<ul><li class="link">Home*</li><li class="link">A &amp; B</li></ul>"#
+        );
+    }
+
+    #[test]
+    fn conditions_of_bool_and_option() {
+        assert!(true.holds());
+        assert!(!false.holds());
+        assert!(Some(0).holds());
+        assert!(!None::<u8>.holds());
+    }
+
+    #[test]
+    fn interpolate_without_holes_does_not_copy() {
+        static BYTES: &[u8] = b"<p>static</p>";
+        const SEGMENTS: &[Segment<'static>] = &[Segment::Static(BYTES)];
+
+        let instance = Instance {
+            namespace: Some("ns-"),
+            ..Instance::default()
+        };
+        let html = interpolate(SEGMENTS, &instance);
+
+        assert_eq!(html, Bytes::from_static(BYTES));
+        assert_eq!(html.as_ptr(), BYTES.as_ptr());
+    }
+
+    #[test]
+    fn interpolate_namespace_and_escaped_bindings() {
+        const SEGMENTS: &[Segment<'static>] = &[
+            Segment::Static(b"<a id=\""),
+            Segment::Namespace,
+            Segment::Static(b"link\" href=\""),
+            Segment::Binding("href"),
+            Segment::Static(b"\">"),
+            Segment::Binding("missing"),
+            Segment::Static(b"</a>"),
+        ];
+
+        let bindings = [("href", "/?a=1&b=2".to_string())];
+        let instance = Instance {
+            namespace: Some("card-1-"),
+            bindings: &bindings,
+            ..Instance::default()
+        };
+        let html = interpolate(SEGMENTS, &instance);
+
+        assert_eq!(html, r#"<a id="card-1-link" href="/?a=1&amp;b=2"></a>"#);
+    }
+}
@@ -1,24 +1,403 @@
+const HTMS_SWAPS = {
+    replace: (element, fragment) => element.replaceWith(fragment),
+    inner: (element, fragment) => element.replaceChildren(fragment),
+    append: (element, fragment) => element.append(fragment),
+    prepend: (element, fragment) => element.prepend(fragment),
+    before: (element, fragment) => element.before(fragment),
+    after: (element, fragment) => element.after(fragment),
+};
+
+// nonce of the runtime script, reused for the scripts it re-creates under a Content-Security-Policy
+const HTMS_NONCE = document.currentScript?.nonce ?? '';
+
+// `type` values the browser executes, data blocks such as `application/ld+json` are left alone
+const HTMS_SCRIPT_TYPES = /^(|module|(text|application)\/(java|ecma)script)$/i;
+
+function htmsDefuseScript(script) {
+    const type = script.getAttribute('type') ?? '';
+
+    if (HTMS_SCRIPT_TYPES.test(type.trim())) {
+        script.setAttribute('data-htms-script', type);
+        script.setAttribute('type', 'text/htms-script');
+    }
+}
+
+function htmsExecuteScripts(nodes) {
+    for (const node of nodes) {
+        if (!(node instanceof Element)) {
+            continue;
+        }
+
+        const scripts = node.matches('script[data-htms-script]')
+            ? [node]
+            : node.querySelectorAll('script[data-htms-script]');
+
+        for (const script of scripts) {
+            const fresh = document.createElement('script');
+            const type = script.getAttribute('data-htms-script');
+
+            for (const {name, value} of script.attributes) {
+                fresh.setAttribute(name, value);
+            }
+
+            fresh.removeAttribute('data-htms-script');
+            type ? fresh.setAttribute('type', type) : fresh.removeAttribute('type');
+            fresh.nonce = script.nonce || HTMS_NONCE;
+            fresh.textContent = script.textContent;
+            script.replaceWith(fresh);
+        }
+    }
+}
+
+function htmsDefuseRecords(records) {
+    for (const record of records) {
+        for (const node of record.addedNodes) {
+            if (node instanceof HTMLScriptElement) {
+                htmsDefuseScript(node);
+            }
+        }
+    }
+}
+
+// task method of a chunk, its `target` without the namespace nor the arguments index
+function htmsTask(chunk) {
+    return chunk.getAttribute('task') ?? chunk.getAttribute('target');
+}
+
+function htmsDispatch(element, name, detail, cancelable = false) {
+    return element.dispatchEvent(new CustomEvent(name, {bubbles: true, cancelable, detail}));
+}
+
+let htmsAnnouncer = null;
+
+// Announce a message to screen readers through a shared, visually hidden live region.
+function htmsAnnounce(message) {
+    if (!htmsAnnouncer?.isConnected) {
+        htmsAnnouncer = document.createElement('div');
+        htmsAnnouncer.className = 'htms-announcer';
+        htmsAnnouncer.setAttribute('role', 'status');
+        htmsAnnouncer.setAttribute('aria-live', 'polite');
+        document.body.append(htmsAnnouncer);
+    }
+
+    const line = document.createElement('p');
+    line.textContent = message;
+    htmsAnnouncer.append(line);
+}
+
+// chunks waiting for their target (or their parent chunk), retried when the DOM changes
+const htmsPending = new Set();
+// ids of the chunks already swapped into the page
+const htmsSwapped = new Set();
+// ids of the chunks dropped by a cancelled `htms:before-swap`, the chunks they parent are dropped too
+const htmsCancelled = new Set();
+
+const htmsObserver = new MutationObserver(() => htmsScheduleRetry());
+let htmsRetryScheduled = false;
+
+function htmsScheduleRetry() {
+    if (htmsRetryScheduled) {
+        return;
+    }
+
+    htmsRetryScheduled = true;
+
+    requestAnimationFrame(() => {
+        htmsRetryScheduled = false;
+
+        for (const chunk of htmsPending) {
+            if (chunk.apply()) {
+                htmsPending.delete(chunk);
+            }
+        }
+
+        if (htmsPending.size === 0) {
+            htmsObserver.disconnect();
+        }
+    });
+}
+
+// Settle a chunk whose swap is cancelled (or whose parent chunk is), so the chunks it parents are
+// dropped instead of waiting for it.
+function htmsCancel(chunk) {
+    chunk.remove();
+    htmsCancelled.add(chunk.getAttribute('target'));
+
+    if (htmsPending.size > 0) {
+        htmsScheduleRetry();
+    }
+}
+
+// Whether the parent chunk of `chunk` is swapped: `true`, still pending: `false`, or cancelled:
+// `null`, in which case `chunk` is cancelled too.
+function htmsParentSettled(chunk) {
+    const parent = chunk.getAttribute('parent');
+
+    if (parent && htmsCancelled.has(parent)) {
+        htmsCancel(chunk);
+        return null;
+    }
+
+    return !parent || htmsSwapped.has(parent);
+}
+
+function htmsQueue(chunk) {
+    if (htmsPending.size === 0) {
+        htmsObserver.observe(document.documentElement, {childList: true, subtree: true});
+    }
+
+    htmsPending.add(chunk);
+}
+
 class HTMSChunk extends HTMLElement {
     connectedCallback() {
+        // scripts must not run while the chunk is parsed, they are defused before the parser
+        // reaches `</script>` and only re-created after the swap when the chunk allows it
+        const observer = new MutationObserver((records) => htmsDefuseRecords(records));
+        observer.observe(this, {childList: true, subtree: true});
+
+        requestAnimationFrame(() => {
+            htmsDefuseRecords(observer.takeRecords());
+            observer.disconnect();
+
+            if (!this.apply()) {
+                htmsQueue(this);
+            }
+        });
+    }
+
+    // Swap the chunk into its target, returns `false` when the target is not in the DOM yet.
+    apply() {
         const target = this.getAttribute('target');
-        const targetElement = document.querySelector(`[data-htms="${target}"]`);
+        const task = htmsTask(this);
+        const settled = htmsParentSettled(this);
 
-        if (!targetElement) {
-            console.warn('[htms-chunk] target not found:', target)
-            return;
+        if (settled !== true) {
+            return settled === null;
         }
 
-        requestAnimationFrame(() => {
-            targetElement.outerHTML = this.innerHTML;
+        const elementId = this.getAttribute('element-id');
+        const targetElement = elementId
+            ? document.getElementById(elementId)
+            : document.querySelector(`[data-htms="${target}"]`);
+        const selector = this.getAttribute('selector');
+        const swapElement = selector ? targetElement?.querySelector(selector) : targetElement;
+
+        if (!swapElement) {
+            return false;
+        }
+
+        const swap = HTMS_SWAPS[this.getAttribute('swap') ?? 'replace'];
+
+        if (!swap) {
+            console.warn('[htms-chunk] unknown swap mode:', this.getAttribute('swap'))
             this.remove();
-        });
+            return true;
+        }
+
+        const fragment = document.createDocumentFragment();
+        fragment.append(...this.childNodes);
+        this.remove();
+
+        const nodes = [...fragment.childNodes];
+        const element = fragment.firstElementChild;
+
+        if (!htmsDispatch(swapElement, 'htms:before-swap', {target, task, element}, true)) {
+            htmsCancel(this);
+            return true;
+        }
+
+        swap(swapElement, fragment);
+        htmsSwapped.add(target);
+
+        // the placeholder survives the non-replacing swaps, it is not loading anymore
+        if (targetElement.isConnected) {
+            targetElement.removeAttribute('aria-busy');
+        }
+
+        if (this.hasAttribute('announce')) {
+            htmsAnnounce(this.getAttribute('announce'));
+        }
+
+        if (this.hasAttribute('scripts')) {
+            htmsExecuteScripts(nodes);
+        }
+
+        htmsDispatch(element?.isConnected ? element : document, 'htms:after-swap', {target, task, element});
+
+        // the swapped content may hold the targets of queued chunks
+        if (htmsPending.size > 0) {
+            htmsScheduleRetry();
+        }
+
+        return true;
     }
 }
 
 customElements.define('htms-chunk', HTMSChunk);
 
-function htmsCleanup() {
-    for (const element of document.querySelectorAll('.htms-dirty')) {
-        element.remove();
+class HTMSAttr extends HTMLElement {
+    connectedCallback() {
+        requestAnimationFrame(() => {
+            if (!this.apply()) {
+                htmsQueue(this);
+            }
+        });
+    }
+
+    // Set the attribute on every element bound to the task, returns `false` when none is in the DOM yet.
+    apply() {
+        const target = this.getAttribute('target');
+        const task = htmsTask(this);
+        const settled = htmsParentSettled(this);
+
+        if (settled !== true) {
+            return settled === null;
+        }
+
+        const elements = document.querySelectorAll(`[data-htms-attr~="${target}"]`);
+
+        if (elements.length === 0) {
+            return false;
+        }
+
+        const attribute = this.getAttribute('name');
+        const value = this.getAttribute('value') ?? '';
+        this.remove();
+
+        let patched = false;
+
+        for (const element of elements) {
+            if (!htmsDispatch(element, 'htms:before-swap', {target, task, element, attribute}, true)) {
+                continue;
+            }
+
+            element.setAttribute(attribute, value);
+            patched = true;
+            htmsDispatch(element, 'htms:after-swap', {target, task, element, attribute});
+        }
+
+        if (!patched) {
+            htmsCancel(this);
+            return true;
+        }
+
+        htmsSwapped.add(target);
+
+        if (htmsPending.size > 0) {
+            htmsScheduleRetry();
+        }
+
+        return true;
+    }
+}
+
+customElements.define('htms-attr', HTMSAttr);
+
+// existing head element an incoming one replaces, `undefined` for the elements that are not supported
+function htmsHeadMatch(element) {
+    switch (element.localName) {
+        case 'title':
+            return document.head.querySelector('title');
+        case 'meta': {
+            const key = element.hasAttribute('name') ? 'name' : 'property';
+            const value = element.getAttribute(key);
+            return value ? document.head.querySelector(`meta[${key}="${CSS.escape(value)}"]`) : undefined;
+        }
+        case 'link':
+            return element.getAttribute('rel') === 'canonical'
+                ? document.head.querySelector('link[rel="canonical"]')
+                : undefined;
+        case 'script':
+            if (element.getAttribute('type') !== 'application/ld+json') {
+                return undefined;
+            }
+
+            return element.id ? document.getElementById(element.id) : null;
+        default:
+            return undefined;
+    }
+}
+
+class HTMSHead extends HTMLElement {
+    connectedCallback() {
+        requestAnimationFrame(() => {
+            if (!this.apply()) {
+                htmsQueue(this);
+            }
+        });
+    }
+
+    // Apply the head elements of the chunk, returns `false` while its parent chunk is pending.
+    apply() {
+        const target = this.getAttribute('target');
+        const task = htmsTask(this);
+        const settled = htmsParentSettled(this);
+
+        if (settled !== true) {
+            return settled === null;
+        }
+
+        const elements = [...(this.querySelector('template')?.content.children ?? [])];
+        const element = document.head;
+        this.remove();
+
+        if (!htmsDispatch(element, 'htms:before-swap', {target, task, element}, true)) {
+            htmsCancel(this);
+            return true;
+        }
+
+        for (const incoming of elements) {
+            const existing = htmsHeadMatch(incoming);
+
+            if (existing === undefined) {
+                console.warn('[htms-head] unsupported head element:', incoming.localName);
+            } else if (existing) {
+                existing.replaceWith(incoming);
+            } else {
+                document.head.append(incoming);
+            }
+        }
+
+        htmsSwapped.add(target);
+        htmsDispatch(element, 'htms:after-swap', {target, task, element});
+
+        if (htmsPending.size > 0) {
+            htmsScheduleRetry();
+        }
+
+        return true;
+    }
+}
+
+customElements.define('htms-head', HTMSHead);
+
+class HTMSCleanup extends HTMLElement {
+    connectedCallback() {
+        htmsCleanup();
+        this.remove();
     }
 }
+
+customElements.define('htms-cleanup', HTMSCleanup);
+
+function htmsCleanup() {
+    // queued after the pending swaps, the chunks still waiting for their target are dropped
+    requestAnimationFrame(() => {
+        for (const element of document.querySelectorAll('.htms-dirty')) {
+            element.remove();
+        }
+
+        const pending = [];
+
+        for (const chunk of htmsPending) {
+            pending.push(chunk.getAttribute('target'));
+            console.warn('[htms-chunk] target not found:', chunk.getAttribute('target'));
+            chunk.remove();
+        }
+
+        htmsPending.clear();
+        htmsObserver.disconnect();
+        htmsDispatch(document, 'htms:complete', {pending});
+    });
+}
@@ -1,6 +1,6 @@
 //! Task system for **htms**.
 //!
-//! Defines a [`Task`] abstraction wrapping a future that produces a `String`.
+//! Defines a [`Task`] abstraction wrapping a future that produces an [`Output`].
 //! Useful for scheduling or executing asynchronous jobs identified by an ID.
 //!
 //! # Example
@@ -12,9 +12,82 @@
 //! ```
 
 use futures_core::future::BoxFuture;
+use futures_util::FutureExt;
 
-/// Boxed future returning a `String`.
-pub type TaskFuture = BoxFuture<'static, String>;
+use crate::chunk::ChunkOptions;
+
+/// Boxed future returning an [`Output`].
+pub type TaskFuture = BoxFuture<'static, Output>;
+
+/// Start of the [`critical_marker`] comments.
+pub(crate) const CRITICAL_MARKER_START: &str = "<!--htms:await:";
+/// End of the [`critical_marker`] comments.
+pub(crate) const CRITICAL_MARKER_END: &str = "-->";
+
+/// Comment left in the compiled template where the output of the critical task `id` is inlined.
+#[must_use]
+pub fn critical_marker(id: &str) -> String {
+    format!("{CRITICAL_MARKER_START}{id}{CRITICAL_MARKER_END}")
+}
+
+/// Output of a task: an HTML fragment plus, for critical tasks, response metadata.
+///
+/// # Example
+/// ```rust
+/// use htms_core::task::Output;
+///
+/// let output = Output::new("<p>Not found</p>")
+///     .with_status(404)
+///     .with_header("cache-control", "no-store");
+///
+/// assert_eq!(output.status, Some(404));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Output {
+    /// The HTML fragment.
+    pub html: String,
+    /// Response status code, only honored for critical tasks.
+    pub status: Option<u16>,
+    /// Response headers, only honored for critical tasks.
+    pub headers: Vec<(String, String)>,
+}
+
+impl Output {
+    /// Create an [`Output`] from an HTML fragment.
+    #[must_use]
+    pub fn new<H: Into<String>>(html: H) -> Self {
+        Self {
+            html: html.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Set the response status code.
+    #[must_use]
+    pub const fn with_status(mut self, status: u16) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Add a response header.
+    #[must_use]
+    pub fn with_header<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
+
+impl From<String> for Output {
+    fn from(html: String) -> Self {
+        Self::new(html)
+    }
+}
+
+impl From<&str> for Output {
+    fn from(html: &str) -> Self {
+        Self::new(html)
+    }
+}
 
 /// Represents an asynchronous task with an identifier and a future.
 pub struct Task {
@@ -22,6 +95,8 @@ pub struct Task {
     pub id: String,
     /// The asynchronous computation to be executed.
     pub future: TaskFuture,
+    options: ChunkOptions,
+    critical: bool,
 }
 
 impl Task {
@@ -40,16 +115,85 @@ impl Task {
     {
         Self {
             id: id.into(),
-            future: Box::pin(future),
+            future: future.map(Output::from).boxed(),
+            options: ChunkOptions::default(),
+            critical: false,
+        }
+    }
+
+    /// Create a critical [`Task`], awaited before the template is sent.
+    ///
+    /// Its output is inlined into the template and may set the response status and headers.
+    ///
+    /// # Example
+    /// ```rust
+    /// use htms_core::task::{Output, Task};
+    /// use std::future;
+    ///
+    /// let task = Task::critical("auth", future::ready(Output::new("<p>Hi</p>").with_status(200)));
+    ///
+    /// assert!(task.is_critical());
+    /// ```
+    pub fn critical<I: Into<String>, F, O>(id: I, future: F) -> Self
+    where
+        F: Future<Output = O> + Send + 'static,
+        O: Into<Output> + 'static,
+    {
+        Self {
+            id: id.into(),
+            future: future.map(Into::into).boxed(),
+            options: ChunkOptions::default(),
+            critical: true,
         }
     }
+
+    /// Set the [`ChunkOptions`] of this task.
+    ///
+    /// # Example
+    /// ```rust
+    /// use htms_core::{chunk::{ChunkOptions, Swap}, task::Task};
+    /// use std::future;
+    ///
+    /// let task = Task::new("id", future::ready("done".to_string()))
+    ///     .with_options(ChunkOptions { swap: Swap::Append, ..ChunkOptions::default() });
+    /// ```
+    #[must_use]
+    pub fn with_options(mut self, options: ChunkOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// How the output lands in the page, see [`ChunkOptions`].
+    #[must_use]
+    pub const fn options(&self) -> &ChunkOptions {
+        &self.options
+    }
+
+    /// Whether the task is awaited before the template is sent and inlined into it instead of
+    /// streamed, see [`Task::critical`].
+    #[must_use]
+    pub const fn is_critical(&self) -> bool {
+        self.critical
+    }
+
+    /// Stream the task instead of awaiting it before the template is sent.
+    pub(crate) const fn streamed(mut self) -> Self {
+        self.critical = false;
+        self
+    }
+
+    /// Split the task into its identifier, future and options.
+    pub(crate) fn into_parts(self) -> (String, TaskFuture, ChunkOptions) {
+        (self.id, self.future, self.options)
+    }
 }
 
 #[cfg(test)]
 #[cfg_attr(coverage_nightly, coverage(off))]
 #[allow(clippy::expect_used, clippy::unused_async)]
 mod tests {
-    use super::Task;
+    use super::{Output, Task};
+    use crate::chunk::{ChunkKind, ChunkOptions, Swap};
 
     async fn message_task(message: &str) -> String {
         message.to_string()
@@ -62,12 +206,36 @@ mod tests {
         assert_eq!(task.id, "identifier");
     }
 
+    #[tokio::test]
+    async fn task_new_has_default_options() {
+        let task = Task::new("identifier", message_task("done"));
+
+        assert_eq!(task.options(), &ChunkOptions::default());
+    }
+
+    #[tokio::test]
+    async fn task_with_options_overrides_options() {
+        let options = ChunkOptions {
+            kind: ChunkKind::Content,
+            swap: Swap::After,
+            selector: Some(".content".into()),
+            scripts: true,
+            parent: Some("parent".into()),
+            announce: Some("Loaded".into()),
+            element_id: Some("content".into()),
+            task: Some("content".into()),
+        };
+        let task = Task::new("identifier", message_task("done")).with_options(options.clone());
+
+        assert_eq!(task.options(), &options);
+    }
+
     #[tokio::test]
     async fn task_future_resolves_to_expected_output() {
         let task = Task::new("id", message_task("expected output"));
         let output = task.future.await;
 
-        assert_eq!(output, "expected output");
+        assert_eq!(output.html, "expected output");
     }
 
     #[tokio::test]
@@ -76,6 +244,32 @@ mod tests {
         let handle = tokio::spawn(task.future);
         let output = handle.await.expect("join handle failed");
 
-        assert_eq!(output, "spawned output");
+        assert_eq!(output.html, "spawned output");
+    }
+
+    #[tokio::test]
+    async fn task_critical_keeps_output_metadata() {
+        let task = Task::critical("auth", async {
+            Output::new("<p>Forbidden</p>")
+                .with_status(403)
+                .with_header("cache-control", "no-store")
+        });
+        assert!(task.is_critical());
+
+        let output = task.future.await;
+
+        assert_eq!(output.html, "<p>Forbidden</p>");
+        assert_eq!(output.status, Some(403));
+        assert_eq!(
+            output.headers,
+            [("cache-control".to_string(), "no-store".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn task_new_is_not_critical() {
+        let task = Task::new("identifier", message_task("done"));
+
+        assert!(!task.is_critical());
     }
 }
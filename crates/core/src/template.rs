@@ -4,30 +4,61 @@
 //! This module powers the build-time pipeline used to prepare streamable templates.
 
 use std::{
-    collections::BTreeSet,
-    fs,
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, BTreeSet},
+    error, fs,
     fs::File,
     io::{self, Read, Write},
     path::{Path, PathBuf},
-    result,
+    rc::Rc,
+    result, slice,
     sync::mpsc,
 };
 
 use lol_html::{
-    HtmlRewriter, OutputSink, Settings, element,
+    HtmlRewriter, OutputSink, RewriteStrSettings, Settings, element,
     errors::RewritingError,
     html_content::{ContentType, Element, EndTag},
+    rewrite_str, text,
+};
+use syn::{
+    Ident, Lit, Token,
+    parse::{Parse, ParseStream, Parser},
+    parse_str,
+    punctuated::Punctuated,
+};
+
+use crate::{
+    assets::{AssetMode, HTMS_CHUNK_JS, STYLE_CSS},
+    chunk::{ChunkKind, ChunkOptions, Swap, escape_attribute},
+    render::{
+        END_SECTION_MARKER, MARKER_PREFIX, NAMESPACE_MARKER, binding_marker, each_marker,
+        section_marker,
+    },
+    scope::{scope_attribute, scope_css},
+    segment::{self, Segment},
+    task::critical_marker,
 };
-use syn::{Ident, parse_str};
 
 static CHUCK_BUFFER_SIZE: usize = 16 * 1024;
-static STATIC_STYLE_CSS: &str = include_str!("static/style.css");
-static STATIC_HTMS_CHUNK_JS: &str = include_str!("static/htms_chunk.js");
+
+/// Comment left after `</head>` in compiled templates, where they are split by [`split_head`].
+pub const HEAD_SPLIT_MARKER: &str = "<!--htms:head-->";
+
+/// Default value of [`Options::max_include_depth`].
+pub const DEFAULT_MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Default value of [`Options::max_include_size`], in bytes.
+pub const DEFAULT_MAX_INCLUDE_SIZE: u64 = 1024 * 1024;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("failed to open input path: {0}: {1}")]
     OpenInputPath(PathBuf, #[source] io::Error),
+    #[error("failed to open template root: {0}: {1}")]
+    OpenTemplateRoot(PathBuf, #[source] io::Error),
+    #[error("input path is outside of the template root: {0}: {1}")]
+    InputOutsideRoot(PathBuf, PathBuf),
     #[error("failed to create output directory: {0}: {1}")]
     CreateOutputDirectory(PathBuf, #[source] io::Error),
     #[error("failed to create output file: {0}: {1}")]
@@ -38,7 +69,9 @@ pub enum Error {
     WriteOutputChunk(PathBuf, #[source] RewritingError),
     #[error("failed to write output file chunk: {0}: {1}")]
     WriteOutputFileChunk(PathBuf, #[source] io::Error),
-    #[error("failed to rewrite the template: {1}")]
+    #[error("reserved text '{MARKER_PREFIX}' in the template at byte offset {1}: {0}")]
+    ReservedMarker(PathBuf, usize),
+    #[error("failed to rewrite the template: {0}: {1}")]
     RewriteTemplate(PathBuf, #[source] RewritingError),
     #[error("failed to close rewriter: {0}: {1}")]
     RewriterEnd(PathBuf, #[source] RewritingError),
@@ -50,6 +83,14 @@ pub enum Error {
         offset: usize,
         source: syn::Error,
     },
+    #[error(r#"invalid attribute '{tag}[{name}="{value}"]' at byte offset {offset}: {reason}"#)]
+    InvalidAttributeValue {
+        tag: String,
+        name: String,
+        value: String,
+        offset: usize,
+        reason: String,
+    },
     #[error(r#"failed to include fragment '{tag}[data-htms="{path}"]' at byte offset {offset}: {source}"#)]
     IncludeFragment {
         tag: String,
@@ -57,16 +98,245 @@ pub enum Error {
         offset: usize,
         source: io::Error,
     },
+    #[error(r#"include cycle '{tag}[data-htms="{value}"]' at byte offset {offset}: {}"#, display_chain(.chain))]
+    IncludeCycle {
+        tag: String,
+        value: String,
+        offset: usize,
+        chain: Vec<PathBuf>,
+    },
+    #[error(r#"include outside of the template root '{tag}[data-htms="{value}"]' at byte offset {offset}: {} is not in {}"#, .resolved.display(), .root.display())]
+    IncludeOutsideRoot {
+        tag: String,
+        value: String,
+        offset: usize,
+        resolved: PathBuf,
+        root: PathBuf,
+    },
+    #[error(r#"include too large '{tag}[data-htms="{value}"]' at byte offset {offset}: more than {max_size} bytes"#)]
+    IncludeTooLarge {
+        tag: String,
+        value: String,
+        offset: usize,
+        max_size: u64,
+    },
+    #[error(r#"include too deep '{tag}[data-htms="{value}"]' at byte offset {offset}: more than {max_depth} nested includes"#)]
+    IncludeDepth {
+        tag: String,
+        value: String,
+        offset: usize,
+        max_depth: usize,
+    },
 }
 
 pub type Result<T, E = Error> = result::Result<T, E>;
 
+type BoxError = Box<dyn error::Error + Send + Sync>;
+
 pub type TaskNames = BTreeSet<String>;
 
+pub type TaskOptions = BTreeMap<String, ChunkOptions>;
+
+pub type Bindings = BTreeSet<String>;
+
+/// Conditional sections enclosing each placeholder of a task, the task runs when all the guards of
+/// one placeholder hold.
+pub type TaskGuards = BTreeMap<String, Vec<Vec<Guard>>>;
+
+/// Calls of the task methods, by task name.
+pub type TaskInvocations = BTreeMap<String, Invocation>;
+
+/// The method and the arguments of a task, e.g. `fn:user_card(42)` or `fn:news(db, user)`.
+///
+/// Tasks without literal arguments are named after their method, each distinct list of literal
+/// arguments of a method is a task of its own, named `<method>-<index>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invocation {
+    /// Name of the task method.
+    pub method: String,
+    /// Arguments passed to the method, in order.
+    pub arguments: Vec<Argument>,
+}
+
+impl Invocation {
+    /// Invocation of a method without arguments.
+    fn of(method: &str) -> Self {
+        Self {
+            method: method.to_string(),
+            arguments: Vec::new(),
+        }
+    }
+}
+
+/// An argument of a task, see [`Invocation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Argument {
+    /// A literal, e.g. `42` in `fn:user_card(42)`.
+    Literal {
+        /// Name of the parameter, from `data-htms-arg-<name>`, or `arg<index>` for positional
+        /// arguments.
+        name: String,
+        /// Rust type of the parameter, from the literal: its suffix, or `i64`, `f64`,
+        /// `&'static str`, `bool` and `char`.
+        ty: String,
+        /// The literal, as written in the template.
+        value: String,
+    },
+    /// A field of the template struct, e.g. `db` in `fn:news(db, user)`.
+    ///
+    /// A task taking fields receives only them, instead of the context.
+    Field(String),
+}
+
+impl Argument {
+    /// Whether the argument is a literal.
+    #[must_use]
+    pub const fn is_literal(&self) -> bool {
+        matches!(self, Self::Literal { .. })
+    }
+}
+
+/// A positional argument of a task placeholder, before it is typed.
+enum PositionalArgument {
+    Literal(Lit),
+    Field(Ident),
+}
+
+impl Parse for PositionalArgument {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(Ident) {
+            input.parse().map(Self::Field)
+        } else {
+            input.parse().map(Self::Literal)
+        }
+    }
+}
+
+/// Collections iterated by `each:` sections, by context path.
+pub type Collections = BTreeMap<String, Collection>;
+
+/// A collection iterated by `each:` sections.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Collection {
+    /// Name of the item in the sections, `item` unless set with `each:<name> in <path>`.
+    pub item: String,
+    /// Paths bound in the sections that read the item, e.g. `item` or `item.name`.
+    pub bindings: Bindings,
+    /// Paths of the `if:` and `unless:` sections that read the item.
+    pub conditions: Bindings,
+}
+
+/// A conditional section enclosing a placeholder, see `data-htms="if:..."` and
+/// `data-htms="unless:..."`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Guard {
+    /// Context path of the condition, e.g. `user.is_admin`.
+    pub path: String,
+    /// Whether the section is an `unless:` section, rendered when the condition does not hold.
+    pub negated: bool,
+}
+
+/// Options controlling how templates are built.
+///
+/// Built from [`Options::default`] and the `with_*` setters, new options may be added.
+///
+/// # Example
+/// ```rust
+/// use htms_core::{assets::AssetMode, template::Options};
+///
+/// let options = Options::default()
+///     .with_assets(AssetMode::External)
+///     .with_namespace(true)
+///     .with_max_include_depth(4);
+///
+/// assert_eq!(options.max_include_depth, 4);
+/// ```
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Options {
+    /// How the chunk runtime and its stylesheet are emitted, see [`AssetMode`].
+    pub assets: AssetMode,
+    /// Prefix placeholder ids with a [`NAMESPACE_MARKER`], replaced per render instance.
+    pub namespace: bool,
+    /// Directory the input template and its includes must stay in, absolute `include:/...` paths
+    /// are resolved against it.
+    ///
+    /// When `None`, absolute paths are resolved against the directory of the input template and
+    /// includes are not confined to it, e.g. `include:../partials/nav.html` is allowed.
+    pub root: Option<PathBuf>,
+    /// Maximum number of nested `include:` levels below the input template.
+    pub max_include_depth: usize,
+    /// Maximum size of an included file, in bytes.
+    pub max_include_size: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            assets: AssetMode::default(),
+            namespace: false,
+            root: None,
+            max_include_depth: DEFAULT_MAX_INCLUDE_DEPTH,
+            max_include_size: DEFAULT_MAX_INCLUDE_SIZE,
+        }
+    }
+}
+
+impl Options {
+    /// Set how the chunk runtime and its stylesheet are emitted, see [`Options::assets`].
+    #[must_use]
+    pub const fn with_assets(mut self, assets: AssetMode) -> Self {
+        self.assets = assets;
+        self
+    }
+
+    /// Set whether placeholder ids are namespaced, see [`Options::namespace`].
+    #[must_use]
+    pub const fn with_namespace(mut self, namespace: bool) -> Self {
+        self.namespace = namespace;
+        self
+    }
+
+    /// Set the template root, see [`Options::root`].
+    #[must_use]
+    pub fn with_root<P: Into<PathBuf>>(mut self, root: P) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// Set the maximum number of nested includes, see [`Options::max_include_depth`].
+    #[must_use]
+    pub const fn with_max_include_depth(mut self, max_include_depth: usize) -> Self {
+        self.max_include_depth = max_include_depth;
+        self
+    }
+
+    /// Set the maximum size of an included file, see [`Options::max_include_size`].
+    #[must_use]
+    pub const fn with_max_include_size(mut self, max_include_size: u64) -> Self {
+        self.max_include_size = max_include_size;
+        self
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct Build {
     has_html_tag: bool,
-    task_names: BTreeSet<String>,
+    tasks: Tasks,
+    bindings: Bindings,
+    conditions: Bindings,
+    collections: Collections,
+    compiled: Vec<u8>,
+}
+
+/// Tasks collected from the placeholders.
+#[derive(Debug, Default)]
+struct Tasks {
+    names: TaskNames,
+    options: TaskOptions,
+    critical: TaskNames,
+    guards: TaskGuards,
+    invocations: TaskInvocations,
 }
 
 impl Build {
@@ -79,22 +349,99 @@ impl Build {
     #[inline]
     #[must_use]
     pub const fn task_names(&self) -> &TaskNames {
-        &self.task_names
+        &self.tasks.names
+    }
+
+    /// Chunk options of each task, collected from the placeholder attributes.
+    #[inline]
+    #[must_use]
+    pub const fn task_options(&self) -> &TaskOptions {
+        &self.tasks.options
+    }
+
+    /// Names of the tasks awaited before the template is sent, see `data-htms-await`.
+    #[inline]
+    #[must_use]
+    pub const fn critical_task_names(&self) -> &TaskNames {
+        &self.tasks.critical
+    }
+
+    /// Method and arguments of each task, see [`Invocation`].
+    #[inline]
+    #[must_use]
+    pub const fn task_invocations(&self) -> &TaskInvocations {
+        &self.tasks.invocations
+    }
+
+    /// Conditional sections enclosing the placeholders of each task, see [`TaskGuards`].
+    #[inline]
+    #[must_use]
+    pub const fn task_guards(&self) -> &TaskGuards {
+        &self.tasks.guards
+    }
+
+    /// Context paths (e.g. `user.name`) bound with `text:` and `data-htms-attr-<name>`, see
+    /// [`binding_marker`].
+    #[inline]
+    #[must_use]
+    pub const fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    /// Context paths (e.g. `user.is_admin`) of the `if:` and `unless:` sections, see
+    /// [`section_marker`].
+    #[inline]
+    #[must_use]
+    pub const fn conditions(&self) -> &Bindings {
+        &self.conditions
+    }
+
+    /// Context paths (e.g. `menu.links`) of the `each:` sections, with the item paths they read,
+    /// see [`each_marker`].
+    #[inline]
+    #[must_use]
+    pub const fn collections(&self) -> &Collections {
+        &self.collections
+    }
+
+    /// The compiled template, as written to the output path.
+    #[inline]
+    #[must_use]
+    pub fn compiled(&self) -> &[u8] {
+        &self.compiled
+    }
+
+    /// Segments of the part of the compiled template before [`HEAD_SPLIT_MARKER`], `None` when
+    /// the template has no `<head>`.
+    #[must_use]
+    pub fn head_segments(&self) -> Option<Vec<Segment<'_>>> {
+        split_head(&self.compiled).map(|(head_end, _)| segment::split(&self.compiled[..head_end]))
+    }
+
+    /// Segments of the compiled template, after [`HEAD_SPLIT_MARKER`] when it has a `<head>`.
+    #[must_use]
+    pub fn template_segments(&self) -> Vec<Segment<'_>> {
+        let body_start = split_head(&self.compiled).map_or(0, |(_, body_start)| body_start);
+
+        segment::split(&self.compiled[body_start..])
     }
 }
 
 /// Parse an input HTML file and build the rewritten output.
 ///
 /// This function reads an HTML file, applies **htms** transformations
-/// (inject style/script, handle `data-htms` attributes, include fragments),
+/// (inject style/script, handle `data-htms` attributes, include fragments, wrap layouts),
 /// and writes the resulting HTML to the given output path.
 /// It also collects metadata into a [`Build`] structure.
 ///
 /// # Errors
 /// Returns an [`Error`] if:
-/// - the input file cannot be read,
+/// - the input file cannot be read, or is outside of [`Options::root`],
 /// - the output directory/file cannot be created or written,
 /// - the HTML rewriting fails (invalid attribute or include),
+/// - an include is part of a cycle or nested deeper than [`Options::max_include_depth`],
+/// - an include resolves outside of [`Options::root`] (through `..` or a symlink) or is larger
+///   than [`Options::max_include_size`],
 /// - flushing the output file fails.
 ///
 /// # Panics
@@ -118,12 +465,43 @@ impl Build {
 pub fn parse_and_build<I: AsRef<Path>, A: AsRef<Path>>(
     input_path: I,
     output_path: A,
+) -> Result<Build> {
+    parse_and_build_with_options(input_path, output_path, &Options::default())
+}
+
+/// Same as [`parse_and_build`], with explicit build [`Options`].
+///
+/// # Errors
+/// See [`parse_and_build`].
+///
+/// # Panics
+/// See [`parse_and_build`].
+///
+/// # Example
+/// ```no_run
+/// use htms_core::{assets::AssetMode, template::{parse_and_build_with_options, Options, Result}};
+///
+/// fn main() -> Result<()> {
+///     let options = Options::default().with_assets(AssetMode::External);
+///
+///     parse_and_build_with_options("./index.html", "./index.out.html", &options)?;
+///
+///     Ok(())
+/// }
+/// ```
+pub fn parse_and_build_with_options<I: AsRef<Path>, A: AsRef<Path>>(
+    input_path: I,
+    output_path: A,
+    options: &Options,
 ) -> Result<Build> {
     let input_path = input_path.as_ref();
     let output_path = output_path.as_ref();
 
     let mut input_file =
         File::open(input_path).map_err(|error| Error::OpenInputPath(input_path.into(), error))?;
+    let include_chain = [fs::canonicalize(input_path)
+        .map_err(|error| Error::OpenInputPath(input_path.into(), error))?];
+    let root = template_root(&include_chain[0], options)?;
 
     if let Some(output_directory) = output_path.parent() {
         fs::create_dir_all(output_directory)
@@ -134,10 +512,13 @@ pub fn parse_and_build<I: AsRef<Path>, A: AsRef<Path>>(
         .map_err(|error| Error::CreateOutputFile(output_path.into(), error))?;
 
     let mut build = Build::default();
+    let mut compiled = Vec::new();
     let (error_tx, error_rx) = mpsc::channel();
 
     {
         let dynamic_rewriter_sink = |c: &[u8]| {
+            compiled.extend_from_slice(c);
+
             if let Err(error) = output_file.write_all(c) {
                 #[allow(clippy::expect_used)]
                 error_tx
@@ -146,56 +527,87 @@ pub fn parse_and_build<I: AsRef<Path>, A: AsRef<Path>>(
             }
         };
 
-        let mut dynamic_rewriter = make_dynamic_rewriter(&mut build, dynamic_rewriter_sink);
+        let mut dynamic_rewriter =
+            make_dynamic_rewriter(&mut build, options, dynamic_rewriter_sink);
 
-        let static_rewriter_sink = |c: &[u8]| {
-            if let Err(error) = dynamic_rewriter.write(c) {
-                #[allow(clippy::expect_used)]
-                error_tx
-                    .send(Error::WriteOutputChunk(output_path.into(), error))
-                    .expect("send static sink error");
-            }
-        };
+        let mut html = String::new();
+
+        input_file
+            .read_to_string(&mut html)
+            .map_err(|error| Error::ReadInputChunk(input_path.into(), error))?;
+
+        let html = expand_fragment(&root, &include_chain, &html, options)
+            .map_err(|error| Error::RewriteTemplate(input_path.into(), error))?;
 
-        let mut static_rewriter = make_static_rewriter(input_path, static_rewriter_sink);
+        // author text holding a marker would be read as a hole of the compiled template
+        if let Some(offset) = html.find(MARKER_PREFIX) {
+            return Err(Error::ReservedMarker(input_path.into(), offset));
+        }
 
-        let mut chuck_buffer = [0u8; CHUCK_BUFFER_SIZE];
+        for chunk in html.as_bytes().chunks(CHUCK_BUFFER_SIZE) {
+            dynamic_rewriter
+                .write(chunk)
+                .map_err(|error| Error::WriteOutputChunk(output_path.into(), error))?;
 
-        loop {
             if let Ok(error) = error_rx.try_recv() {
                 return Err(error);
             }
+        }
 
-            let bytes_read = input_file
-                .read(&mut chuck_buffer)
-                .map_err(|error| Error::ReadInputChunk(input_path.into(), error))?;
-
-            if bytes_read == 0 {
-                break;
-            }
+        dynamic_rewriter
+            .end()
+            .map_err(|error| Error::RewriterEnd(output_path.into(), error))?;
 
-            static_rewriter
-                .write(&chuck_buffer[..bytes_read])
-                .map_err(|error| Error::RewriteTemplate(input_path.into(), error))?;
+        if let Ok(error) = error_rx.try_recv() {
+            return Err(error);
         }
     }
 
     output_file
         .flush()
         .map_err(|error| Error::FlushOutputFile(output_path.into(), error))?;
+    build.compiled = compiled;
 
     Ok(build)
 }
 
-fn make_dynamic_rewriter<O: OutputSink>(
-    build: &'_ mut Build,
+/// Find where a compiled template splits between its head and the rest of the document.
+///
+/// Returns the byte ranges `(..head_end, body_start..)` around the [`HEAD_SPLIT_MARKER`], or `None`
+/// when the template has no `<head>`.
+#[must_use]
+pub fn split_head(compiled: &[u8]) -> Option<(usize, usize)> {
+    compiled
+        .windows(HEAD_SPLIT_MARKER.len())
+        .position(|window| window == HEAD_SPLIT_MARKER.as_bytes())
+        .map(|head_end| (head_end, head_end + HEAD_SPLIT_MARKER.len()))
+}
+
+fn make_dynamic_rewriter<'a, O: OutputSink>(
+    build: &'a mut Build,
+    options: &'a Options,
     rewriter_sink: O,
-) -> HtmlRewriter<'_, O> {
+) -> HtmlRewriter<'a, O> {
+    let Build {
+        has_html_tag,
+        tasks,
+        bindings,
+        conditions,
+        collections,
+        ..
+    } = build;
+    // sections enclosing the current element, closed by their end tag handler
+    let sections: OpenSections = Rc::new(RefCell::new(Vec::new()));
+    // whether the current element is in the `<head>`, where awaited head outputs are inlined
+    let in_head = Rc::new(Cell::new(false));
+    let head_in_head = Rc::clone(&in_head);
+    let head_sections = Rc::clone(&sections);
+
     HtmlRewriter::new(
         Settings {
             element_content_handlers: vec![
-                element!("html", |el: &mut Element| {
-                    build.has_html_tag = true;
+                element!("html", move |el: &mut Element| {
+                    *has_html_tag = true;
 
                     if let Some(handlers) = el.end_tag_handlers() {
                         handlers.push(Box::new(|end: &mut EndTag| {
@@ -206,9 +618,7 @@ fn make_dynamic_rewriter<O: OutputSink>(
                     Ok(())
                 }),
                 element!("body", |el: &mut Element| {
-                    el.append(r#"<script class="htms-dirty">"#, ContentType::Html);
-                    el.append(STATIC_HTMS_CHUNK_JS, ContentType::Html);
-                    el.append("</script>", ContentType::Html);
+                    el.append(&script_tag(options.assets), ContentType::Html);
 
                     if let Some(handlers) = el.end_tag_handlers() {
                         handlers.push(Box::new(move |end: &mut EndTag| {
@@ -218,29 +628,69 @@ fn make_dynamic_rewriter<O: OutputSink>(
                     }
                     Ok(())
                 }),
-                element!("html>head", |el| {
-                    el.append(r#"<style class="htms-dirty">"#, ContentType::Html);
-                    el.append(STATIC_STYLE_CSS, ContentType::Html);
-                    el.append("</style>", ContentType::Html);
+                element!("html>head", move |el: &mut Element| {
+                    el.append(&style_tag(options.assets), ContentType::Html);
+                    open_head(el, &head_in_head, &head_sections);
 
                     Ok(())
                 }),
-                element!(r#"[data-htms^="fn:"]"#, |el| {
-                    let attribute_value = el.get_attribute("data-htms").unwrap_or_default();
-                    let (_, method_name) =
-                        attribute_value.trim().split_once(':').unwrap_or_default();
-
-                    if let Err(source) = parse_str::<Ident>(method_name) {
-                        return Err(Error::InvalidHtmsAttribute {
-                            tag: el.tag_name(),
-                            offset: el.source_location().bytes().start,
-                            source,
-                        }
-                        .into());
+                element!("*", move |el| {
+                    let modifier = el.get_attribute("data-htms").unwrap_or_default();
+                    let modifier = modifier.trim();
+                    let mut paths = Vec::new();
+
+                    // the section is open for the bindings and tasks of its own element
+                    let section = if modifier.starts_with("if:") || modifier.starts_with("unless:")
+                    {
+                        Some(collect_section(el, &sections)?)
+                    } else if modifier.starts_with("each:") {
+                        Some(collect_each(el, collections, &sections)?)
+                    } else {
+                        None
+                    };
+                    let open_sections = sections
+                        .borrow()
+                        .iter()
+                        .map(|(section, _)| section.clone())
+                        .collect::<Vec<_>>();
+
+                    if modifier.starts_with("text:") {
+                        paths.push(collect_text_binding(el)?);
+                    }
+
+                    paths.extend(collect_attribute_value_bindings(el)?);
+
+                    for path in paths {
+                        match item_collection(&open_sections, collections, &path) {
+                            Some(collection) => collection.bindings.insert(path),
+                            None => bindings.insert(path),
+                        };
+                    }
+
+                    if let Some((Section::Guard(Guard { path, .. }), _)) = &section {
+                        match item_collection(&open_sections, collections, path) {
+                            Some(collection) => collection.conditions.insert(path.clone()),
+                            None => conditions.insert(path.clone()),
+                        };
+                    }
+
+                    if modifier.starts_with("fn:") {
+                        let guards = section_guards(el, &open_sections)?;
+                        collect_task_placeholder(el, tasks, &guards, id_prefix(options))?;
+                    } else if modifier.starts_with("head:") {
+                        let guards = section_guards(el, &open_sections)?;
+                        collect_head_placeholder(el, tasks, &guards, in_head.get())?;
+                    }
+
+                    if el.has_attribute("data-htms-attr") {
+                        let guards = section_guards(el, &open_sections)?;
+                        collect_attribute_bindings(el, tasks, &guards, id_prefix(options))?;
                     }
 
-                    el.set_attribute("data-htms", method_name)?;
-                    build.task_names.insert(method_name.to_string());
+                    // without an end tag, the section only holds its own element
+                    if let Some((_, false)) = section {
+                        sections.borrow_mut().pop();
+                    }
 
                     Ok(())
                 }),
@@ -251,139 +701,2204 @@ fn make_dynamic_rewriter<O: OutputSink>(
     )
 }
 
-fn make_static_rewriter<O: OutputSink>(
-    input_path: &'_ Path,
-    static_rewriter_sink: O,
-) -> HtmlRewriter<'_, O> {
-    HtmlRewriter::new(
-        Settings {
-            element_content_handlers: vec![element!(r#"[data-htms^="include:"]"#, |el| {
-                let attribute_value = el.get_attribute("data-htms").unwrap_or_default();
-                let (_, file_path) = attribute_value.trim().split_once(':').unwrap_or_default();
-
-                let include_path = Path::new(input_path).with_file_name(file_path);
-                let html =
-                    fs::read_to_string(include_path).map_err(|source| Error::IncludeFragment {
-                        tag: el.tag_name(),
-                        path: file_path.into(),
-                        offset: el.source_location().bytes().start,
-                        source,
-                    })?;
+/// Mark the `<head>` as open until its end tag, after which the [`HEAD_SPLIT_MARKER`] is left.
+///
+/// The head and the rest of the document are written apart, so no section can enclose the
+/// `</head>`.
+fn open_head(el: &mut Element, in_head: &Rc<Cell<bool>>, sections: &OpenSections) {
+    in_head.set(true);
 
-                el.replace(html.as_str(), ContentType::Html);
+    let Some(handlers) = el.end_tag_handlers() else {
+        return;
+    };
+    let in_head = Rc::clone(in_head);
+    let sections = Rc::clone(sections);
 
-                Ok(())
-            })],
-            ..Settings::default()
-        },
-        static_rewriter_sink,
-    )
-}
+    handlers.push(Box::new(move |end: &mut EndTag| {
+        if let Some((_, start)) = sections.borrow().first() {
+            return Err(start.invalid("sections cannot enclose the </head>").into());
+        }
 
-#[cfg(test)]
-#[cfg_attr(coverage_nightly, coverage(off))]
-#[allow(clippy::expect_used, clippy::panic, clippy::unwrap_used)]
-mod parse_and_build {
-    use std::{
-        fs,
-        io::Write,
-        path::PathBuf,
-        time::{SystemTime, UNIX_EPOCH},
-    };
+        in_head.set(false);
+        end.after(HEAD_SPLIT_MARKER, ContentType::Html);
+        Ok(())
+    }));
+}
 
-    use super::{Build, STATIC_HTMS_CHUNK_JS, STATIC_STYLE_CSS, parse_and_build};
-    use crate::template;
+/// A section enclosing the current element.
+#[derive(Debug, Clone)]
+enum Section {
+    /// An `if:` or `unless:` section.
+    Guard(Guard),
+    /// An `each:` section, repeated for each item of the collection at `path`.
+    Each { item: String, path: String },
+}
 
-    fn unique_path(prefix: &str, extension: &str) -> PathBuf {
-        let nanos = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("get nanos since epoch")
-            .as_nanos();
+/// The attribute that opened a section, to report a section spanning the `</head>`.
+#[derive(Debug, Clone)]
+struct SectionStart {
+    tag: String,
+    value: String,
+    offset: usize,
+}
 
-        std::env::temp_dir().join(format!("{prefix}_{nanos}.{extension}"))
+impl SectionStart {
+    fn invalid(&self, reason: &str) -> Error {
+        Error::InvalidAttributeValue {
+            tag: self.tag.clone(),
+            name: "data-htms".into(),
+            value: self.value.clone(),
+            offset: self.offset,
+            reason: reason.into(),
+        }
     }
+}
 
-    fn write_temp_file(prefix: &str, contents: &str) -> PathBuf {
-        let path = unique_path(prefix, "html");
+/// Sections enclosing the current element, with the attributes that opened them.
+type OpenSections = Rc<RefCell<Vec<(Section, SectionStart)>>>;
 
-        let mut file = fs::File::create(&path).expect("create temp file");
+/// Surround an `if:path` or `unless:path` element with the [`section_marker`] of `path` and the
+/// [`END_SECTION_MARKER`], the section is open for the placeholders nested in the element.
+///
+/// Returns the section and whether it is closed by an end tag.
+fn collect_section(el: &mut Element, sections: &OpenSections) -> Result<(Section, bool)> {
+    let attribute_value = el.get_attribute("data-htms").unwrap_or_default();
+    let (modifier, path) = attribute_value.trim().split_once(':').unwrap_or_default();
+    let negated = modifier == "unless";
 
-        file.write_all(contents.as_bytes())
-            .expect("write temp file");
+    parse_binding_path(path)
+        .map_err(|reason| invalid_attribute_value(el, "data-htms", &attribute_value, reason))?;
+    el.before(&section_marker(path, negated), ContentType::Html);
 
-        path
-    }
+    let section = Section::Guard(Guard {
+        path: path.to_string(),
+        negated,
+    });
 
-    fn write_input_file(contents: &str) -> PathBuf {
-        write_temp_file("htms_test_input", contents)
-    }
+    Ok((section.clone(), open_section(el, section, sections)))
+}
 
-    fn read_output_string(path: &PathBuf) -> String {
-        fs::read_to_string(path).expect("read output file")
-    }
+/// Surround an `each:items` (or `each:item in items`) element with the [`each_marker`] of `items`
+/// and the [`END_SECTION_MARKER`], the bindings and conditions reading `item` in the element are
+/// recorded in the [`Collection`].
+///
+/// Returns the section and whether it is closed by an end tag.
+fn collect_each(
+    el: &mut Element,
+    collections: &mut Collections,
+    sections: &OpenSections,
+) -> Result<(Section, bool)> {
+    let attribute_value = el.get_attribute("data-htms").unwrap_or_default();
+    let invalid =
+        |el: &Element, reason| invalid_attribute_value(el, "data-htms", &attribute_value, reason);
+    let (_, expression) = attribute_value.trim().split_once(':').unwrap_or_default();
+    let (item, path) = expression
+        .split_once(" in ")
+        .map_or(("item", expression), |(item, path)| {
+            (item.trim(), path.trim())
+        });
 
-    fn temp_build(input_html: &str) -> (PathBuf, template::Result<Build>) {
-        let input_path = write_input_file(input_html);
-        let output_path = unique_path("htms_test_output", "html");
-        let build = parse_and_build(&input_path, &output_path);
+    parse_str::<Ident>(item)
+        .map_err(|error| invalid(el, format!("invalid item name '{item}': {error}")))?;
+    parse_binding_path(path).map_err(|reason| invalid(el, reason))?;
 
-        (output_path, build)
+    if sections
+        .borrow()
+        .iter()
+        .any(|(section, _)| matches!(section, Section::Each { .. }))
+    {
+        return Err(invalid(el, "each: sections cannot be nested".into()));
     }
 
-    fn temp_build_with_rendered(input_html: &str) -> (Build, String) {
-        let (output_path, build) = temp_build(input_html);
-        let rendered = read_output_string(&output_path);
+    let collection = collections
+        .entry(path.to_string())
+        .or_insert_with(|| Collection {
+            item: item.to_string(),
+            ..Collection::default()
+        });
 
-        (build.expect("temp_build"), rendered)
+    if collection.item != item {
+        return Err(invalid(
+            el,
+            format!(
+                "collection '{path}' is already iterated as '{}'",
+                collection.item
+            ),
+        ));
     }
 
-    #[test]
-    fn without_html_tag_does_not_inject_style_and_script() {
-        let (build, rendered) = temp_build_with_rendered("<p>Content</p>");
+    el.before(&each_marker(path), ContentType::Html);
 
-        assert!(!build.has_html_tag());
-        assert!(!rendered.contains("<style>"));
-        assert!(!rendered.contains("<script>"));
-        assert!(rendered.contains("<p>Content</p>"));
-    }
+    let section = Section::Each {
+        item: item.to_string(),
+        path: path.to_string(),
+    };
 
-    #[test]
-    fn with_html_tag_injects_style_and_script_and_removes_end_tags() {
-        let (build, rendered) = temp_build_with_rendered(
-            r"<!doctype html><html><head></head><body>
-            <p>Content</p>
-            </body></html>",
-        );
+    Ok((section.clone(), open_section(el, section, sections)))
+}
 
-        assert!(build.has_html_tag());
-        assert!(
-            rendered.contains(
-                format!(r#"<style class="htms-dirty">{STATIC_STYLE_CSS}</style>"#).as_str()
-            )
-        );
-        assert!(rendered.contains(
-            format!(r#"<script class="htms-dirty">{STATIC_HTMS_CHUNK_JS}</script>"#).as_str()
-        ));
-        assert!(!rendered.contains("</body>"));
+/// Close the section after the element and open it until its end tag, if any.
+fn open_section(el: &mut Element, section: Section, sections: &OpenSections) -> bool {
+    let start = SectionStart {
+        tag: el.tag_name(),
+        value: el.get_attribute("data-htms").unwrap_or_default(),
+        offset: el.source_location().bytes().start,
+    };
+
+    el.after(END_SECTION_MARKER, ContentType::Html);
+    el.remove_attribute("data-htms");
+    sections.borrow_mut().push((section, start));
+
+    let Some(handlers) = el.end_tag_handlers() else {
+        return false;
+    };
+    let sections = Rc::clone(sections);
+
+    handlers.push(Box::new(move |_: &mut EndTag| {
+        sections.borrow_mut().pop();
+        Ok(())
+    }));
+
+    true
+}
+
+/// The collection of the enclosing `each:` section, when `path` reads its item.
+fn item_collection<'c>(
+    sections: &[Section],
+    collections: &'c mut Collections,
+    path: &str,
+) -> Option<&'c mut Collection> {
+    let root = path.split('.').next().unwrap_or_default();
+
+    let path = sections.iter().find_map(|section| match section {
+        Section::Each { item, path } if item == root => Some(path),
+        _ => None,
+    })?;
+
+    collections.get_mut(path)
+}
+
+/// Guards of the sections enclosing a task placeholder, which cannot be repeated by `each:`.
+fn section_guards(el: &Element, sections: &[Section]) -> Result<Vec<Guard>> {
+    sections
+        .iter()
+        .map(|section| match section {
+            Section::Guard(guard) => Ok(guard.clone()),
+            Section::Each { path, .. } => Err(invalid_attribute_value(
+                el,
+                "data-htms",
+                &el.get_attribute("data-htms").unwrap_or_default(),
+                format!("task placeholders cannot be repeated by 'each:{path}'"),
+            )),
+        })
+        .collect()
+}
+
+fn collect_task_placeholder(
+    el: &mut Element,
+    tasks: &mut Tasks,
+    guards: &[Guard],
+    id_prefix: &str,
+) -> Result<()> {
+    let attribute_value = el.get_attribute("data-htms").unwrap_or_default();
+    let (_, call) = attribute_value.trim().split_once(':').unwrap_or_default();
+    let (method_name, arguments) = call
+        .split_once('(')
+        .map_or((call, None), |(name, rest)| (name.trim_end(), Some(rest)));
+
+    if let Err(source) = parse_str::<Ident>(method_name) {
+        return Err(Error::InvalidHtmsAttribute {
+            tag: el.tag_name(),
+            offset: el.source_location().bytes().start,
+            source,
+        });
+    }
+
+    let arguments = parse_task_arguments(el, arguments)
+        .map_err(|reason| invalid_attribute_value(el, "data-htms", &attribute_value, reason))?;
+    let invocation = Invocation {
+        method: method_name.to_string(),
+        arguments,
+    };
+    let task_name = invocation_task_name(tasks, &invocation);
+    let options = parse_chunk_options(el, id_prefix)?;
+    let critical = parse_critical(el, &options)?;
+
+    check_critical(tasks, method_name, critical)
+        .map_err(|reason| invalid_attribute_value(el, "data-htms", &attribute_value, reason))?;
+
+    let swap = options.swap;
+    let by_id = options.element_id.is_some();
+
+    insert_task(tasks, &task_name, invocation, options, guards)
+        .map_err(|reason| invalid_attribute_value(el, "data-htms", &attribute_value, reason))?;
+    el.set_attribute("data-htms", &format!("{id_prefix}{task_name}"))
+        .map_err(|error| {
+            invalid_attribute_value(el, "data-htms", &attribute_value, error.to_string())
+        })?;
+    set_loading_state(el, !critical)?;
+
+    // the chunk targets the element by its `id`, the placeholder attribute is not needed anymore
+    if by_id {
+        el.remove_attribute("data-htms");
+    }
+
+    if critical {
+        insert_critical_marker(el, &task_name, swap);
+        tasks.critical.insert(task_name);
+    }
+
+    Ok(())
+}
+
+/// Parse the arguments of a task placeholder: the positional literals and fields of
+/// `fn:name(...)` (the rest of the attribute after `(`), or the `data-htms-arg-<name>` literals,
+/// whose attributes are removed.
+fn parse_task_arguments(
+    el: &mut Element,
+    positional: Option<&str>,
+) -> result::Result<Vec<Argument>, String> {
+    let named = el
+        .attributes()
+        .iter()
+        .filter_map(|attribute| {
+            let name = attribute.name();
+            let parameter = name.strip_prefix("data-htms-arg-")?.to_string();
+
+            Some((name, parameter, attribute.value()))
+        })
+        .collect::<Vec<_>>();
+
+    let Some(positional) = positional else {
+        let mut arguments = Vec::with_capacity(named.len());
+
+        for (attribute, name, value) in named {
+            let name = name.replace('-', "_");
+
+            parse_str::<Ident>(&name)
+                .map_err(|error| format!("invalid argument name '{name}': {error}"))?;
+            let value = unescape_attribute(&value);
+            let literal = parse_str::<Lit>(value.trim())
+                .map_err(|error| format!("invalid argument '{name}' literal '{value}': {error}"))?;
+
+            arguments.push(typed_argument(name, &literal)?);
+            el.remove_attribute(&attribute);
+        }
+
+        return Ok(arguments);
+    };
+
+    if !named.is_empty() {
+        return Err("arguments are passed either in parentheses or with data-htms-arg-*".into());
+    }
+
+    let Some(positional) = positional.trim_end().strip_suffix(')') else {
+        return Err("missing ')' after the task arguments".into());
+    };
+    let mut arguments = Vec::new();
+
+    for (index, argument) in Punctuated::<PositionalArgument, Token![,]>::parse_terminated
+        .parse_str(&unescape_attribute(positional))
+        .map_err(|error| format!("invalid task arguments '{positional}': {error}"))?
+        .iter()
+        .enumerate()
+    {
+        arguments.push(match argument {
+            PositionalArgument::Literal(literal) => typed_argument(format!("arg{index}"), literal)?,
+            PositionalArgument::Field(field) => {
+                let field = field.to_string();
+
+                if arguments.contains(&Argument::Field(field.clone())) {
+                    return Err(format!("field '{field}' is passed twice"));
+                }
+
+                Argument::Field(field)
+            },
+        });
+    }
+
+    Ok(arguments)
+}
+
+/// Type an argument from its literal, see [`Argument::Literal`].
+fn typed_argument(name: String, literal: &Lit) -> result::Result<Argument, String> {
+    let (ty, value) = match literal {
+        Lit::Int(int) if int.suffix().is_empty() => ("i64", int.token()),
+        Lit::Float(float) if float.suffix().is_empty() => ("f64", float.token()),
+        Lit::Int(int) => (int.suffix(), int.token()),
+        Lit::Float(float) => (float.suffix(), float.token()),
+        Lit::Str(string) => ("&'static str", string.token()),
+        Lit::Char(char) => ("char", char.token()),
+        Lit::Bool(bool) => {
+            return Ok(Argument::Literal {
+                name,
+                ty: "bool".to_string(),
+                value: bool.value.to_string(),
+            });
+        },
+        _ => {
+            return Err(format!(
+                "unsupported argument '{name}', expected a number, a string, a bool or a char literal"
+            ));
+        },
+    };
+
+    Ok(Argument::Literal {
+        name,
+        ty: ty.to_string(),
+        value: value.to_string(),
+    })
+}
+
+/// Name of the task of an invocation: the method without literal arguments, the task of the same
+/// call if any, otherwise the next `<method>-<index>`.
+fn invocation_task_name(tasks: &Tasks, invocation: &Invocation) -> String {
+    if !invocation.arguments.iter().any(Argument::is_literal) {
+        return invocation.method.clone();
+    }
+
+    if let Some((name, _)) = tasks
+        .invocations
+        .iter()
+        .find(|(_, existing)| *existing == invocation)
+    {
+        return name.clone();
+    }
+
+    let calls = tasks
+        .invocations
+        .values()
+        .filter(|existing| {
+            existing.method == invocation.method
+                && existing.arguments.iter().any(Argument::is_literal)
+        })
+        .count();
+
+    format!("{}-{calls}", invocation.method)
+}
+
+/// Read the `data-htms-await` flag of a task placeholder.
+///
+/// Critical outputs are inlined at build-time positions, so they cannot target a child node or
+/// wait for a parent chunk.
+fn parse_critical(el: &mut Element, options: &ChunkOptions) -> Result<bool> {
+    let Some(value) = el.get_attribute("data-htms-await") else {
+        return Ok(false);
+    };
+
+    let critical = parse_flag(&value)
+        .map_err(|reason| invalid_attribute_value(el, "data-htms-await", &value, reason))?;
+
+    if critical && (options.selector.is_some() || options.parent.is_some()) {
+        return Err(invalid_attribute_value(
+            el,
+            "data-htms-await",
+            &value,
+            "critical placeholders cannot use data-htms-selector or data-htms-parent".into(),
+        ));
+    }
+
+    el.remove_attribute("data-htms-await");
+
+    Ok(critical)
+}
+
+/// Check that the placeholders of a task method are all awaited or all streamed.
+fn check_critical(tasks: &Tasks, method_name: &str, critical: bool) -> result::Result<(), String> {
+    if tasks.invocations.iter().any(|(name, existing)| {
+        existing.method == method_name && tasks.critical.contains(name) != critical
+    }) {
+        return Err(format!("task '{method_name}' is both awaited and streamed"));
+    }
+
+    Ok(())
+}
+
+/// Leave the marker replaced by the output of a critical task where its swap mode puts it.
+fn insert_critical_marker(el: &mut Element, method_name: &str, swap: Swap) {
+    let marker = critical_marker(method_name);
+
+    match swap {
+        Swap::Replace => el.replace(&marker, ContentType::Html),
+        Swap::Inner => el.set_inner_content(&marker, ContentType::Html),
+        Swap::Append => el.append(&marker, ContentType::Html),
+        Swap::Prepend => el.prepend(&marker, ContentType::Html),
+        Swap::Before => el.before(&marker, ContentType::Html),
+        Swap::After => el.after(&marker, ContentType::Html),
+    }
+}
+
+/// Mark a placeholder as loading for assistive technologies.
+///
+/// `aria-busy="true"` is added unless the placeholder sets `data-htms-busy="false"` or its own
+/// `aria-busy`, and `data-htms-live` becomes `aria-live`. The runtime clears `aria-busy` once the
+/// chunk is swapped. Placeholders that are not `loading` (critical ones) never get `aria-busy`.
+fn set_loading_state(el: &mut Element, loading: bool) -> Result<()> {
+    let mut busy = loading;
+
+    if let Some(value) = el.get_attribute("data-htms-busy") {
+        busy &= parse_flag(&value)
+            .map_err(|reason| invalid_attribute_value(el, "data-htms-busy", &value, reason))?;
+        el.remove_attribute("data-htms-busy");
+    }
+
+    if busy && !el.has_attribute("aria-busy") {
+        el.set_attribute("aria-busy", "true")
+            .map_err(|error| invalid_attribute_value(el, "aria-busy", "true", error.to_string()))?;
+    }
+
+    if let Some(value) = el.get_attribute("data-htms-live") {
+        let live = value.trim();
+
+        if !matches!(live, "polite" | "assertive" | "off") {
+            return Err(invalid_attribute_value(
+                el,
+                "data-htms-live",
+                &value,
+                format!(r#"unknown live mode '{live}', expected "polite", "assertive" or "off""#),
+            ));
+        }
+
+        el.set_attribute("aria-live", live)
+            .map_err(|error| invalid_attribute_value(el, "aria-live", live, error.to_string()))?;
+        el.remove_attribute("data-htms-live");
+    }
+
+    Ok(())
+}
+
+/// Prefix written before the ids of the placeholders.
+const fn id_prefix(options: &Options) -> &'static str {
+    if options.namespace {
+        NAMESPACE_MARKER
+    } else {
+        ""
+    }
+}
+
+/// Collect a `head:` placeholder, the task output updates `document.head` so the element is removed.
+///
+/// An awaited (`data-htms-await`) placeholder is replaced by the marker of its critical output,
+/// inlined in the `<head>` by the renders that hold the head until the critical tasks are done.
+fn collect_head_placeholder(
+    el: &mut Element,
+    tasks: &mut Tasks,
+    guards: &[Guard],
+    in_head: bool,
+) -> Result<()> {
+    let attribute_value = el.get_attribute("data-htms").unwrap_or_default();
+    let (_, method_name) = attribute_value.trim().split_once(':').unwrap_or_default();
+
+    if let Err(source) = parse_str::<Ident>(method_name) {
+        return Err(Error::InvalidHtmsAttribute {
+            tag: el.tag_name(),
+            offset: el.source_location().bytes().start,
+            source,
+        });
+    }
+
+    let options = ChunkOptions {
+        kind: ChunkKind::Head,
+        ..ChunkOptions::default()
+    };
+    let critical = parse_critical(el, &options)?;
+
+    if critical && !in_head {
+        return Err(invalid_attribute_value(
+            el,
+            "data-htms",
+            &attribute_value,
+            "awaited head placeholders must be in the <head>".into(),
+        ));
+    }
+
+    check_critical(tasks, method_name, critical)
+        .and_then(|()| {
+            insert_task(
+                tasks,
+                method_name,
+                Invocation::of(method_name),
+                options,
+                guards,
+            )
+        })
+        .map_err(|reason| invalid_attribute_value(el, "data-htms", &attribute_value, reason))?;
+
+    if critical {
+        el.replace(&critical_marker(method_name), ContentType::Html);
+        tasks.critical.insert(method_name.to_string());
+    } else {
+        el.remove();
+    }
+
+    Ok(())
+}
+
+/// Collect the `attribute:task` bindings of a `data-htms-attr` attribute.
+///
+/// The attribute is rewritten to the space-separated task names, which the runtime matches with
+/// `[data-htms-attr~="task"]`.
+fn collect_attribute_bindings(
+    el: &mut Element,
+    tasks: &mut Tasks,
+    guards: &[Guard],
+    id_prefix: &str,
+) -> Result<()> {
+    let attribute_value = el.get_attribute("data-htms-attr").unwrap_or_default();
+    let bindings = parse_attribute_bindings(&attribute_value).map_err(|reason| {
+        invalid_attribute_value(el, "data-htms-attr", &attribute_value, reason)
+    })?;
+    let mut method_names = Vec::with_capacity(bindings.len());
+
+    for (name, method_name) in bindings {
+        let options = ChunkOptions {
+            kind: ChunkKind::Attribute(name.to_string()),
+            ..ChunkOptions::default()
+        };
+
+        insert_task(
+            tasks,
+            method_name,
+            Invocation::of(method_name),
+            options,
+            guards,
+        )
+        .map_err(|reason| {
+            invalid_attribute_value(el, "data-htms-attr", &attribute_value, reason)
+        })?;
+        method_names.push(format!("{id_prefix}{method_name}"));
+    }
+
+    el.set_attribute("data-htms-attr", &method_names.join(" "))
+        .map_err(|error| {
+            invalid_attribute_value(el, "data-htms-attr", &attribute_value, error.to_string())
+        })?;
+
+    Ok(())
+}
+
+/// Replace the content of a `text:path` element with the [`binding_marker`] of `path`.
+///
+/// Returns the bound path.
+fn collect_text_binding(el: &mut Element) -> Result<String> {
+    let attribute_value = el.get_attribute("data-htms").unwrap_or_default();
+    let (_, path) = attribute_value.trim().split_once(':').unwrap_or_default();
+
+    parse_binding_path(path)
+        .map_err(|reason| invalid_attribute_value(el, "data-htms", &attribute_value, reason))?;
+    el.set_inner_content(&binding_marker(path), ContentType::Text);
+    el.remove_attribute("data-htms");
+
+    Ok(path.to_string())
+}
+
+/// Replace each `data-htms-attr-<name>="path"` attribute with a `name` attribute holding the
+/// [`binding_marker`] of `path`.
+///
+/// Returns the bound paths.
+fn collect_attribute_value_bindings(el: &mut Element) -> Result<Vec<String>> {
+    let attributes = el
+        .attributes()
+        .iter()
+        .filter_map(|attribute| {
+            let name = attribute.name();
+            let target = name.strip_prefix("data-htms-attr-")?.to_string();
+
+            Some((name, target, attribute.value()))
+        })
+        .collect::<Vec<_>>();
+    let mut paths = Vec::with_capacity(attributes.len());
+
+    for (name, target, path) in attributes {
+        parse_binding_path(&path)
+            .map_err(|reason| invalid_attribute_value(el, &name, &path, reason))?;
+        el.remove_attribute(&name);
+        el.set_attribute(&target, &binding_marker(&path))
+            .map_err(|error| invalid_attribute_value(el, &name, &path, error.to_string()))?;
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+/// Check that a binding path is a dot-separated list of identifiers, e.g. `user.name`.
+fn parse_binding_path(path: &str) -> result::Result<(), String> {
+    for segment in path.split('.') {
+        parse_str::<Ident>(segment)
+            .map_err(|error| format!("invalid binding path segment '{segment}': {error}"))?;
+    }
+
+    Ok(())
+}
+
+/// Record a task, the first placeholder wins but a task cannot update both content and attributes.
+///
+/// The `guards` of each placeholder are kept, so the task only runs when one of them is rendered.
+/// Every invocation of a method must pass the same parameters, the method has a single signature.
+fn insert_task(
+    tasks: &mut Tasks,
+    method_name: &str,
+    invocation: Invocation,
+    options: ChunkOptions,
+    guards: &[Guard],
+) -> result::Result<(), String> {
+    let mut options = options;
+
+    if invocation.method != method_name {
+        options.task = Some(invocation.method.clone());
+    }
+
+    if let Some(existing) = tasks.options.get(method_name)
+        && existing.kind != options.kind
+    {
+        return Err(match &existing.kind {
+            ChunkKind::Content => format!("task '{method_name}' is already a content placeholder"),
+            ChunkKind::Attribute(name) => {
+                format!("task '{method_name}' already patches the '{name}' attribute")
+            },
+            ChunkKind::Head => format!("task '{method_name}' is already a head placeholder"),
+        });
+    }
+
+    if let Some(existing) = tasks.options.get(method_name)
+        && *existing != options
+    {
+        return Err(format!(
+            "task '{method_name}' is placed with different options: '{}' and '{}'",
+            existing.attributes().trim_start(),
+            options.attributes().trim_start()
+        ));
+    }
+
+    if let Some(existing) = tasks
+        .invocations
+        .values()
+        .find(|existing| existing.method == invocation.method)
+        && parameters(existing) != parameters(&invocation)
+    {
+        return Err(format!(
+            "task '{}' is called with ({}) and with ({})",
+            invocation.method,
+            parameters(existing).join(", "),
+            parameters(&invocation).join(", ")
+        ));
+    }
+
+    tasks.names.insert(method_name.to_string());
+    tasks
+        .invocations
+        .entry(method_name.to_string())
+        .or_insert(invocation);
+    tasks.options.insert(method_name.to_string(), options);
+
+    let placeholders = tasks.guards.entry(method_name.to_string()).or_default();
+
+    if !placeholders.iter().any(|existing| existing == guards) {
+        placeholders.push(guards.to_vec());
+    }
+
+    Ok(())
+}
+
+/// Parameters of the method of an invocation, e.g. `id: i64` or `db` for a field.
+fn parameters(invocation: &Invocation) -> Vec<String> {
+    invocation
+        .arguments
+        .iter()
+        .map(|argument| match argument {
+            Argument::Literal { name, ty, .. } => format!("{name}: {ty}"),
+            Argument::Field(field) => field.clone(),
+        })
+        .collect()
+}
+
+fn parse_chunk_options(el: &mut Element, id_prefix: &str) -> Result<ChunkOptions> {
+    let mut options = ChunkOptions::default();
+
+    if let Some(swap) = el.get_attribute("data-htms-swap") {
+        options.swap = swap
+            .trim()
+            .parse()
+            .map_err(|reason| invalid_attribute_value(el, "data-htms-swap", &swap, reason))?;
+        el.remove_attribute("data-htms-swap");
+    }
+
+    if let Some(selector) = el.get_attribute("data-htms-selector") {
+        let trimmed = selector.trim();
+
+        if trimmed.is_empty() {
+            return Err(invalid_attribute_value(
+                el,
+                "data-htms-selector",
+                &selector,
+                "empty selector".into(),
+            ));
+        }
+
+        check_selector(trimmed).map_err(|reason| {
+            invalid_attribute_value(el, "data-htms-selector", &selector, reason)
+        })?;
+
+        options.selector = Some(trimmed.to_string());
+        el.remove_attribute("data-htms-selector");
+    }
+
+    if let Some(scripts) = el.get_attribute("data-htms-scripts") {
+        options.scripts = parse_flag(&scripts)
+            .map_err(|reason| invalid_attribute_value(el, "data-htms-scripts", &scripts, reason))?;
+        el.remove_attribute("data-htms-scripts");
+    }
+
+    if let Some(parent) = el.get_attribute("data-htms-parent") {
+        let trimmed = parent.trim();
+
+        parse_str::<Ident>(trimmed).map_err(|error| {
+            invalid_attribute_value(el, "data-htms-parent", &parent, error.to_string())
+        })?;
+
+        options.parent = Some(trimmed.to_string());
+        el.remove_attribute("data-htms-parent");
+    }
+
+    if let Some(target) = el.get_attribute("data-htms-target") {
+        match target.trim() {
+            "data" => {},
+            "id" => {
+                let id = el
+                    .get_attribute("id")
+                    .map(|id| id.trim().to_string())
+                    .filter(|id| !id.is_empty())
+                    .ok_or_else(|| {
+                        invalid_attribute_value(
+                            el,
+                            "data-htms-target",
+                            &target,
+                            "the placeholder has no id".into(),
+                        )
+                    })?;
+
+                // namespaced like the chunk ids, so each instance targets its own element
+                if !id_prefix.is_empty() {
+                    el.set_attribute("id", &format!("{id_prefix}{id}"))
+                        .map_err(|error| {
+                            invalid_attribute_value(el, "id", &id, error.to_string())
+                        })?;
+                }
+
+                options.element_id = Some(id);
+            },
+            other => {
+                return Err(invalid_attribute_value(
+                    el,
+                    "data-htms-target",
+                    &target,
+                    format!(r#"unknown target '{other}', expected "data" or "id""#),
+                ));
+            },
+        }
+
+        el.remove_attribute("data-htms-target");
+    }
+
+    if let Some(announce) = el.get_attribute("data-htms-announce") {
+        let trimmed = announce.trim();
+
+        if trimmed.is_empty() {
+            return Err(invalid_attribute_value(
+                el,
+                "data-htms-announce",
+                &announce,
+                "empty announcement".into(),
+            ));
+        }
+
+        options.announce = Some(trimmed.to_string());
+        el.remove_attribute("data-htms-announce");
+    }
+
+    Ok(options)
+}
+
+/// Parse the whitespace-separated `attribute:task` pairs of a `data-htms-attr` attribute.
+fn parse_attribute_bindings(value: &str) -> result::Result<Vec<(&str, &str)>, String> {
+    let bindings = value
+        .split_whitespace()
+        .map(|binding| {
+            let (name, method_name) = binding
+                .split_once(':')
+                .ok_or_else(|| format!("invalid binding '{binding}', expected 'attribute:task'"))?;
+
+            if name.is_empty()
+                || name.chars().any(|c| {
+                    c.is_control() || matches!(c, '"' | '\'' | '<' | '>' | '/' | '=' | ':')
+                })
+            {
+                return Err(format!("invalid attribute name '{name}'"));
+            }
+
+            parse_str::<Ident>(method_name)
+                .map_err(|error| format!("invalid task name '{method_name}': {error}"))?;
+
+            Ok((name, method_name))
+        })
+        .collect::<result::Result<Vec<_>, String>>()?;
+
+    if bindings.is_empty() {
+        return Err("expected at least one 'attribute:task' binding".into());
+    }
+
+    Ok(bindings)
+}
+
+/// Light syntactic check of a CSS selector, the browser parses it with `querySelector`.
+///
+/// Brackets, parentheses and quotes must be balanced, and no compound selector may be empty
+/// around a combinator or a comma, e.g. `.metric >` or `a,,b`.
+fn check_selector(selector: &str) -> result::Result<(), String> {
+    let mut closing = Vec::new();
+    let mut quote = None;
+    let mut expects_compound = true;
+    let mut chars = selector.chars();
+
+    while let Some(c) = chars.next() {
+        if let Some(open) = quote {
+            match c {
+                '\\' => {
+                    chars.next();
+                },
+                c if c == open => quote = None,
+                _ => {},
+            }
+            continue;
+        }
+
+        match c {
+            '\\' => {
+                chars.next();
+            },
+            '"' | '\'' => quote = Some(c),
+            '(' => closing.push(')'),
+            '[' => closing.push(']'),
+            ')' | ']' if closing.pop() != Some(c) => {
+                return Err(format!("unbalanced '{c}'"));
+            },
+            '>' | '+' | '~' | ',' if closing.is_empty() => {
+                if expects_compound {
+                    return Err(format!("missing selector before '{c}'"));
+                }
+                expects_compound = true;
+                continue;
+            },
+            _ => {},
+        }
+
+        if !c.is_whitespace() {
+            expects_compound = false;
+        }
+    }
+
+    if quote.is_some() {
+        return Err("unterminated string".into());
+    }
+
+    if let Some(c) = closing.last() {
+        return Err(format!("missing '{c}'"));
+    }
+
+    if expects_compound {
+        return Err("missing selector at the end".into());
+    }
+
+    Ok(())
+}
+
+/// Parse a boolean attribute: present without value (or `true`) enables it, `false` disables it.
+fn parse_flag(value: &str) -> result::Result<bool, String> {
+    match value.trim() {
+        "" | "true" => Ok(true),
+        "false" => Ok(false),
+        other => Err(format!(
+            "invalid flag '{other}', expected no value, \"true\" or \"false\""
+        )),
+    }
+}
+
+fn invalid_attribute_value(el: &Element, name: &str, value: &str, reason: String) -> Error {
+    Error::InvalidAttributeValue {
+        tag: el.tag_name(),
+        name: name.into(),
+        value: value.into(),
+        offset: el.source_location().bytes().start,
+        reason,
+    }
+}
+
+fn script_tag(assets: AssetMode) -> String {
+    match assets {
+        AssetMode::Inline => format!(
+            r#"<script class="htms-dirty">{}</script>"#,
+            HTMS_CHUNK_JS.contents()
+        ),
+        AssetMode::External => format!(
+            r#"<script class="htms-dirty" src="{}" integrity="{}" crossorigin="anonymous"></script>"#,
+            HTMS_CHUNK_JS.path(),
+            HTMS_CHUNK_JS.integrity()
+        ),
+    }
+}
+
+fn style_tag(assets: AssetMode) -> String {
+    match assets {
+        AssetMode::Inline => format!(
+            r#"<style class="htms-dirty">{}</style>"#,
+            STYLE_CSS.contents()
+        ),
+        AssetMode::External => format!(
+            r#"<link class="htms-dirty" rel="stylesheet" href="{}" integrity="{}" crossorigin="anonymous">"#,
+            STYLE_CSS.path(),
+            STYLE_CSS.integrity()
+        ),
+    }
+}
+
+/// Replace `include:` elements with the contents of the included file, and mark the content of
+/// `wrap:` elements so [`wrap_layouts`] can put it in the slots of their layout, pushed to `layouts`.
+///
+/// `include_chain` holds the canonical paths of the files being expanded, the input template first.
+fn make_static_rewriter<'a, O: OutputSink>(
+    root: &'a Path,
+    include_chain: &'a [PathBuf],
+    options: &'a Options,
+    layouts: &'a mut Vec<String>,
+    static_rewriter_sink: O,
+) -> HtmlRewriter<'a, O> {
+    HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![
+                element!(r#"[data-htms^="include:"]"#, move |el| {
+                    let html = read_fragment(el, root, include_chain, options)?;
+
+                    el.replace(html.as_str(), ContentType::Html);
+
+                    Ok(())
+                }),
+                element!(r#"[data-htms^="wrap:"]"#, move |el| {
+                    let index = layouts.len();
+
+                    layouts.push(read_fragment(el, root, include_chain, options)?);
+                    el.before(&format!("<!--htms:wrap:{index}-->"), ContentType::Html);
+                    el.after(&format!("<!--htms:/wrap:{index}-->"), ContentType::Html);
+                    el.remove_and_keep_content();
+
+                    Ok(())
+                }),
+            ],
+            ..Settings::default()
+        },
+        static_rewriter_sink,
+    )
+}
+
+/// Read the file of an `include:` or `wrap:` element and expand it, see [`expand_fragment`].
+///
+/// Paths are resolved relative to the including file, or to `root` when absolute, and no fragment
+/// may leave `root` when it is set by [`Options::root`].
+fn read_fragment(
+    el: &Element,
+    root: &Path,
+    include_chain: &[PathBuf],
+    options: &Options,
+) -> result::Result<String, BoxError> {
+    let value = el.get_attribute("data-htms").unwrap_or_default();
+    let (_, file_path) = value.trim().split_once(':').unwrap_or_default();
+    let offset = el.source_location().bytes().start;
+    let include_error = |source| Error::IncludeFragment {
+        tag: el.tag_name(),
+        path: file_path.into(),
+        offset,
+        source,
+    };
+
+    let including_path = include_chain
+        .last()
+        .map_or_else(|| Path::new(""), PathBuf::as_path);
+    let include_path = Path::new(file_path).strip_prefix("/").map_or_else(
+        |_| including_path.with_file_name(file_path),
+        |root_relative| root.join(root_relative),
+    );
+    // canonical paths have no `..` and no symlinks left, a prefix check is enough
+    let include_path = fs::canonicalize(include_path).map_err(include_error)?;
+
+    if options.root.is_some() && !include_path.starts_with(root) {
+        return Err(Error::IncludeOutsideRoot {
+            tag: el.tag_name(),
+            value,
+            offset,
+            resolved: include_path,
+            root: root.into(),
+        }
+        .into());
+    }
+
+    if include_chain.contains(&include_path) {
+        let mut chain = include_chain.to_vec();
+        chain.push(include_path);
+
+        return Err(Error::IncludeCycle {
+            tag: el.tag_name(),
+            value,
+            offset,
+            chain,
+        }
+        .into());
+    }
+
+    if include_chain.len() > options.max_include_depth {
+        return Err(Error::IncludeDepth {
+            tag: el.tag_name(),
+            value,
+            offset,
+            max_depth: options.max_include_depth,
+        }
+        .into());
+    }
+
+    // read through the opened file, so the size checked is the size read
+    let mut html = String::new();
+    File::open(&include_path)
+        .and_then(|file| {
+            file.take(options.max_include_size.saturating_add(1))
+                .read_to_string(&mut html)
+        })
+        .map_err(include_error)?;
+
+    if html.len() as u64 > options.max_include_size {
+        return Err(Error::IncludeTooLarge {
+            tag: el.tag_name(),
+            value,
+            offset,
+            max_size: options.max_include_size,
+        }
+        .into());
+    }
+    let html = substitute_props(&html, &fragment_props(el));
+    let scope_key = include_path.strip_prefix(root).unwrap_or(&include_path);
+
+    scope_fragment(&html, &scope_attribute(&scope_key.to_string_lossy()))
+        .and_then(|html| {
+            expand_fragment(
+                root,
+                &[include_chain, slice::from_ref(&include_path)].concat(),
+                &html,
+                options,
+            )
+        })
+        .map_err(|error| match error {
+            // keep the error of the innermost fragment instead of nesting rewriting errors
+            RewritingError::ContentHandlerError(source) => source,
+            error => Error::RewriteTemplate(include_path.clone(), error).into(),
+        })
+}
+
+/// Scope the `<style data-htms-scoped>` of a fragment to its own elements, see [`scope_css`].
+///
+/// Fragments without scoped styles are returned as is.
+fn scope_fragment(html: &str, attribute: &str) -> result::Result<String, RewritingError> {
+    if !html.contains("data-htms-scoped") {
+        return Ok(html.to_string());
+    }
+
+    let mut css = String::new();
+
+    rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("*", |el| {
+                    if el.tag_name() == "style" {
+                        el.remove_attribute("data-htms-scoped");
+                    } else if el.tag_name() != "script" {
+                        el.set_attribute(attribute, "")?;
+                    }
+
+                    Ok(())
+                }),
+                text!("style[data-htms-scoped]", |chunk| {
+                    css.push_str(chunk.as_str());
+
+                    if chunk.last_in_text_node() {
+                        chunk.replace(&scope_css(&css, attribute), ContentType::Html);
+                        css.clear();
+                    } else {
+                        chunk.remove();
+                    }
+
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::new()
+        },
+    )
+}
+
+/// Props passed to a fragment with `data-htms-prop-<name>="value"`, decoded from the attribute.
+fn fragment_props(el: &Element) -> BTreeMap<String, String> {
+    el.attributes()
+        .iter()
+        .filter_map(|attribute| {
+            let name = attribute.name();
+            let name = name.strip_prefix("data-htms-prop-")?;
+
+            Some((name.to_string(), unescape_attribute(&attribute.value())))
+        })
+        .collect()
+}
+
+/// Replace each `{{ name }}` of `html` with the escaped value of the prop `name`.
+///
+/// References to props that were not passed are left as is, so fragments can contain other
+/// `{{ ... }}` syntaxes.
+fn substitute_props(html: &str, props: &BTreeMap<String, String>) -> String {
+    if props.is_empty() {
+        return html.to_string();
+    }
+
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}").map(|end| start + end) else {
+            break;
+        };
+
+        output.push_str(&rest[..start]);
+
+        match props.get(rest[start + 2..end].trim()) {
+            Some(value) => output.push_str(&escape_attribute(value)),
+            None => output.push_str(&rest[start..end + 2]),
+        }
+
+        rest = &rest[end + 2..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Decode the character references [`escape_attribute`] produces, plus `&#39;` and `&apos;`.
+fn unescape_attribute(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Expand the includes and layouts of a fragment, whose path is the last of `include_chain`.
+fn expand_fragment(
+    root: &Path,
+    include_chain: &[PathBuf],
+    html: &str,
+    options: &Options,
+) -> result::Result<String, RewritingError> {
+    let mut output = Vec::with_capacity(html.len());
+    let mut layouts = Vec::new();
+
+    let mut rewriter =
+        make_static_rewriter(root, include_chain, options, &mut layouts, |c: &[u8]| {
+            output.extend_from_slice(c);
+        });
+    rewriter.write(html.as_bytes())?;
+    rewriter.end()?;
+
+    wrap_layouts(String::from_utf8_lossy(&output).into_owned(), &layouts)
+}
+
+/// Replace the content marked by [`make_static_rewriter`] for each `wrap:` element with its layout,
+/// whose slots are filled with that content.
+fn wrap_layouts(mut html: String, layouts: &[String]) -> result::Result<String, RewritingError> {
+    for (index, layout) in layouts.iter().enumerate() {
+        let start_marker = format!("<!--htms:wrap:{index}-->");
+        let end_marker = format!("<!--htms:/wrap:{index}-->");
+
+        let (Some(start), Some(end)) = (html.find(&start_marker), html.find(&end_marker)) else {
+            continue;
+        };
+
+        let wrapped = fill_slots(layout, &html[start + start_marker.len()..end])?;
+        html.replace_range(start..end + end_marker.len(), &wrapped);
+    }
+
+    Ok(html)
+}
+
+/// Replace the `<slot>` elements of `layout` with the matching part of `content`, or with their
+/// own content when `content` has nothing for them.
+fn fill_slots(layout: &str, content: &str) -> result::Result<String, RewritingError> {
+    let slots = slot_contents(content)?;
+
+    rewrite_str(
+        &close_slot_tags(layout)?,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!("slot", |el| {
+                let name = el.get_attribute("name").unwrap_or_default();
+
+                match slots.get(&name) {
+                    Some(content) => el.replace(content, ContentType::Html),
+                    None => el.remove_and_keep_content(),
+                }
+
+                Ok(())
+            })],
+            ..RewriteStrSettings::new()
+        },
+    )
+}
+
+/// Split the content of a `wrap:` element by slot name, top-level elements with a `slot` attribute
+/// go to the named slot and everything else to the default slot (named `""`).
+fn slot_contents(content: &str) -> result::Result<BTreeMap<String, String>, RewritingError> {
+    let mut names = Vec::new();
+
+    let marked = rewrite_str(
+        &format!("<htms-slots>{content}</htms-slots>"),
+        RewriteStrSettings {
+            element_content_handlers: vec![element!("htms-slots > [slot]", |el| {
+                let index = names.len();
+
+                names.push(el.get_attribute("slot").unwrap_or_default());
+                el.remove_attribute("slot");
+                el.before(&format!("<!--htms:slot:{index}-->"), ContentType::Html);
+                el.after(&format!("<!--htms:/slot:{index}-->"), ContentType::Html);
+
+                Ok(())
+            })],
+            ..RewriteStrSettings::new()
+        },
+    )?;
+
+    let mut rest = marked
+        .trim_start_matches("<htms-slots>")
+        .trim_end_matches("</htms-slots>")
+        .to_string();
+    let mut slots = BTreeMap::<String, String>::new();
+
+    for (index, name) in names.into_iter().enumerate() {
+        let start_marker = format!("<!--htms:slot:{index}-->");
+        let end_marker = format!("<!--htms:/slot:{index}-->");
+
+        let (Some(start), Some(end)) = (rest.find(&start_marker), rest.find(&end_marker)) else {
+            continue;
+        };
+
+        slots
+            .entry(name)
+            .or_default()
+            .push_str(&rest[start + start_marker.len()..end]);
+        rest.replace_range(start..end + end_marker.len(), "");
+    }
+
+    if !rest.trim().is_empty() {
+        slots.entry(String::new()).or_default().insert_str(0, &rest);
+    }
+
+    Ok(slots)
+}
+
+/// Rewrite `<slot/>` as `<slot></slot>`, the HTML parser ignores the self-closing flag of non-void
+/// elements and would take everything after it as the slot content.
+fn close_slot_tags(layout: &str) -> result::Result<String, RewritingError> {
+    let mut self_closing = Vec::new();
+
+    rewrite_str(
+        layout,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!("slot", |el| {
+                if el.is_self_closing() {
+                    self_closing.push(el.source_location().bytes());
+                }
+
+                Ok(())
+            })],
+            ..RewriteStrSettings::new()
+        },
+    )?;
+
+    let mut layout = layout.to_string();
+
+    for range in self_closing.into_iter().rev() {
+        let start_tag = layout[range.clone()]
+            .trim_end_matches('>')
+            .trim_end_matches('/');
+        let closed = format!("{}></slot>", start_tag.trim_end());
+        layout.replace_range(range, &closed);
+    }
+
+    Ok(layout)
+}
+
+/// Canonical template root, checked to contain the canonical `input_path` when it is set by
+/// [`Options::root`], the directory of `input_path` otherwise.
+fn template_root(input_path: &Path, options: &Options) -> Result<PathBuf> {
+    let root = match &options.root {
+        Some(root) => {
+            fs::canonicalize(root).map_err(|error| Error::OpenTemplateRoot(root.into(), error))?
+        },
+        None => input_path.parent().unwrap_or(input_path).into(),
+    };
+
+    if !input_path.starts_with(&root) {
+        return Err(Error::InputOutsideRoot(input_path.into(), root));
+    }
+
+    Ok(root)
+}
+
+fn display_chain(chain: &[PathBuf]) -> String {
+    chain
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[allow(clippy::expect_used, clippy::panic, clippy::unwrap_used)]
+mod parse_and_build {
+    use std::{
+        fs,
+        io::Write,
+        path::PathBuf,
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use super::{
+        Argument, Build, Guard, Options, check_selector, parse_and_build,
+        parse_and_build_with_options, split_head,
+    };
+    use crate::{
+        assets::{AssetMode, HTMS_CHUNK_JS, STYLE_CSS},
+        chunk::{ChunkKind, Swap},
+        scope::scope_attribute,
+        segment::Segment,
+        template,
+    };
+
+    fn unique_path(prefix: &str, extension: &str) -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("get nanos since epoch")
+            .as_nanos();
+
+        std::env::temp_dir().join(format!("{prefix}_{nanos}.{extension}"))
+    }
+
+    fn write_temp_file(prefix: &str, contents: &str) -> PathBuf {
+        let path = unique_path(prefix, "html");
+
+        let mut file = fs::File::create(&path).expect("create temp file");
+
+        file.write_all(contents.as_bytes())
+            .expect("write temp file");
+
+        path
+    }
+
+    fn write_input_file(contents: &str) -> PathBuf {
+        write_temp_file("htms_test_input", contents)
+    }
+
+    fn read_output_string(path: &PathBuf) -> String {
+        fs::read_to_string(path).expect("read output file")
+    }
+
+    fn temp_build(input_html: &str) -> (PathBuf, template::Result<Build>) {
+        let input_path = write_input_file(input_html);
+        let output_path = unique_path("htms_test_output", "html");
+        let build = parse_and_build(&input_path, &output_path);
+
+        (output_path, build)
+    }
+
+    fn temp_build_with_rendered(input_html: &str) -> (Build, String) {
+        let (output_path, build) = temp_build(input_html);
+        let rendered = read_output_string(&output_path);
+
+        (build.expect("temp_build"), rendered)
+    }
+
+    #[test]
+    fn without_html_tag_does_not_inject_style_and_script() {
+        let (build, rendered) = temp_build_with_rendered("<p>Content</p>");
+
+        assert!(!build.has_html_tag());
+        assert!(!rendered.contains("<style>"));
+        assert!(!rendered.contains("<script>"));
+        assert!(rendered.contains("<p>Content</p>"));
+    }
+
+    #[test]
+    fn with_html_tag_injects_style_and_script_and_removes_end_tags() {
+        let (build, rendered) = temp_build_with_rendered(
+            r"<!doctype html><html><head></head><body>
+            <p>Content</p>
+            </body></html>",
+        );
+
+        assert!(build.has_html_tag());
+        assert!(
+            rendered.contains(
+                format!(
+                    r#"<style class="htms-dirty">{}</style>"#,
+                    STYLE_CSS.contents()
+                )
+                .as_str()
+            )
+        );
+        assert!(
+            rendered.contains(
+                format!(
+                    r#"<script class="htms-dirty">{}</script>"#,
+                    HTMS_CHUNK_JS.contents()
+                )
+                .as_str()
+            )
+        );
+        assert!(!rendered.contains("</body>"));
         assert!(!rendered.contains("</html>"));
     }
 
     #[test]
-    fn collects_task_names_and_normalizes_attribute() {
+    fn with_external_assets_references_hashed_files_with_integrity() {
+        let input_path = write_input_file(
+            r"<!doctype html><html><head></head><body>
+            <p>Content</p>
+            </body></html>",
+        );
+        let output_path = unique_path("htms_test_output", "html");
+        let options = Options {
+            assets: AssetMode::External,
+            ..Options::default()
+        };
+        parse_and_build_with_options(&input_path, &output_path, &options).expect("parse and build");
+        let rendered = read_output_string(&output_path);
+
+        assert!(rendered.contains(
+            format!(
+                r#"<link class="htms-dirty" rel="stylesheet" href="{}" integrity="{}" crossorigin="anonymous">"#,
+                STYLE_CSS.path(),
+                STYLE_CSS.integrity()
+            )
+            .as_str()
+        ));
+        assert!(rendered.contains(
+            format!(
+                r#"<script class="htms-dirty" src="{}" integrity="{}" crossorigin="anonymous"></script>"#,
+                HTMS_CHUNK_JS.path(),
+                HTMS_CHUNK_JS.integrity()
+            )
+            .as_str()
+        ));
+        assert!(!rendered.contains(STYLE_CSS.contents()));
+        assert!(!rendered.contains(HTMS_CHUNK_JS.contents()));
+    }
+
+    #[test]
+    fn collects_task_names_and_normalizes_attribute() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:news"></div>
+            <div data-htms="fn:blog_posts"></div>
+            </body></html>"#,
+        );
+        let task_names = build.task_names();
+
+        assert!(task_names.contains("news"));
+        assert!(task_names.contains("blog_posts"));
+        assert!(rendered.contains(r#"data-htms="news""#));
+        assert!(rendered.contains(r#"data-htms="blog_posts""#));
+        assert!(!rendered.contains(r#"data-htms="fn:news""#));
+        assert!(!rendered.contains(r#"data-htms="fn:blog_posts""#));
+    }
+
+    #[test]
+    fn collects_swap_mode_and_removes_attribute() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head></head><body>
+            <ul data-htms="fn:items" data-htms-swap="append"></ul>
+            <div data-htms="fn:news"></div>
+            </body></html>"#,
+        );
+        let task_options = build.task_options();
+
+        assert_eq!(task_options["items"].swap, Swap::Append);
+        assert_eq!(task_options["news"].swap, Swap::Replace);
+        assert!(rendered.contains(r#"<ul data-htms="items" aria-busy="true"></ul>"#));
+        assert!(!rendered.contains("data-htms-swap"));
+    }
+
+    #[test]
+    fn collects_selector_and_removes_attribute() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head></head><body>
+            <div class="card" data-htms="fn:user_stats" data-htms-selector=" .metric "><div class="metric"></div></div>
+            <div data-htms="fn:news"></div>
+            </body></html>"#,
+        );
+        let task_options = build.task_options();
+
+        assert_eq!(
+            task_options["user_stats"].selector.as_deref(),
+            Some(".metric")
+        );
+        assert_eq!(task_options["news"].selector, None);
+        assert!(rendered.contains(r#"<div class="card" data-htms="user_stats" aria-busy="true">"#));
+        assert!(!rendered.contains("data-htms-selector"));
+    }
+
+    #[test]
+    fn fails_on_invalid_selector() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:news" data-htms-selector=".metric >"></div>
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains(
+            r#"invalid attribute 'div[data-htms-selector=".metric >"]' at byte offset 53: missing selector at the end"#
+        ));
+    }
+
+    #[test]
+    fn checks_selectors_syntax_only() {
+        for selector in [
+            "li:first-child",
+            ":is(.a, .b) > span",
+            "ul > li:nth-of-type(2n + 1)",
+            r#"a[href$=".pdf" i]"#,
+            "dl dt ~ dd",
+            "#a\\:b",
+        ] {
+            assert_eq!(check_selector(selector), Ok(()), "{selector}");
+        }
+
+        for (selector, reason) in [
+            (".metric >", "missing selector at the end"),
+            ("> .metric", "missing selector before '>'"),
+            ("a,,b", "missing selector before ','"),
+            (":is(.a", "missing ')'"),
+            ("a]", "unbalanced ']'"),
+            ("a[title='x]", "unterminated string"),
+        ] {
+            assert_eq!(
+                check_selector(selector),
+                Err(reason.to_string()),
+                "{selector}"
+            );
+        }
+    }
+
+    #[test]
+    fn fails_on_empty_selector() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:news" data-htms-selector=" "></div>
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains("empty selector"));
+    }
+
+    #[test]
+    fn collects_scripts_flag_and_removes_attribute() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:widget" data-htms-scripts></div>
+            <div data-htms="fn:chart" data-htms-scripts="true"></div>
+            <div data-htms="fn:news" data-htms-scripts="false"></div>
+            <div data-htms="fn:blog"></div>
+            </body></html>"#,
+        );
+        let task_options = build.task_options();
+
+        assert!(task_options["widget"].scripts);
+        assert!(task_options["chart"].scripts);
+        assert!(!task_options["news"].scripts);
+        assert!(!task_options["blog"].scripts);
+        assert!(!rendered.contains("data-htms-scripts"));
+    }
+
+    #[test]
+    fn fails_on_invalid_scripts_flag() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:news" data-htms-scripts="yes"></div>
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains("invalid flag 'yes'"));
+    }
+
+    #[test]
+    fn collects_parent_and_removes_attribute() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:post"><div data-htms="fn:comments" data-htms-parent="post"></div></div>
+            </body></html>"#,
+        );
+        let task_options = build.task_options();
+
+        assert_eq!(task_options["comments"].parent.as_deref(), Some("post"));
+        assert_eq!(task_options["post"].parent, None);
+        assert!(!rendered.contains("data-htms-parent"));
+    }
+
+    #[test]
+    fn fails_on_invalid_parent() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:comments" data-htms-parent="blog-post"></div>
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains(
+            r#"invalid attribute 'div[data-htms-parent="blog-post"]' at byte offset 53: "#
+        ));
+    }
+
+    #[test]
+    fn collects_attribute_bindings() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head></head><body>
+            <img data-htms-attr="src:avatar_url alt:avatar_alt" src="/placeholder.png">
+            <progress data-htms-attr="value:upload_progress" max="100"></progress>
+            </body></html>"#,
+        );
+        let task_options = build.task_options();
+
+        assert_eq!(
+            build.task_names().iter().collect::<Vec<_>>(),
+            ["avatar_alt", "avatar_url", "upload_progress"]
+        );
+        assert_eq!(
+            task_options["avatar_url"].kind,
+            ChunkKind::Attribute("src".into())
+        );
+        assert_eq!(
+            task_options["upload_progress"].kind,
+            ChunkKind::Attribute("value".into())
+        );
+        assert!(
+            rendered
+                .contains(r#"<img data-htms-attr="avatar_url avatar_alt" src="/placeholder.png">"#)
+        );
+    }
+
+    #[test]
+    fn collects_attribute_bindings_on_task_placeholder() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head></head><body>
+            <a data-htms="fn:profile" data-htms-attr="href:profile_url"></a>
+            </body></html>"#,
+        );
+        let task_options = build.task_options();
+
+        assert_eq!(task_options["profile"].kind, ChunkKind::Content);
+        assert_eq!(
+            task_options["profile_url"].kind,
+            ChunkKind::Attribute("href".into())
+        );
+        assert!(
+            rendered.contains(
+                r#"<a data-htms="profile" data-htms-attr="profile_url" aria-busy="true">"#
+            )
+        );
+    }
+
+    #[test]
+    fn fails_on_invalid_attribute_binding() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms-attr="src"></div>
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains(
+            r#"invalid attribute 'div[data-htms-attr="src"]' at byte offset 53: invalid binding 'src'"#
+        ));
+    }
+
+    #[test]
+    fn fails_on_invalid_attribute_binding_task() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <img data-htms-attr="src:avatar-url">
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains("invalid task name 'avatar-url'"));
+    }
+
+    #[test]
+    fn fails_on_task_bound_to_content_and_attribute() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:avatar"></div>
+            <img data-htms-attr="src:avatar">
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains("task 'avatar' is already a content placeholder"));
+    }
+
+    #[test]
+    fn fails_on_task_bound_to_two_attributes() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <img data-htms-attr="src:avatar alt:avatar">
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains("task 'avatar' already patches the 'src' attribute"));
+    }
+
+    #[test]
+    fn collects_head_placeholder_and_removes_element() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head><title>Loading...</title>
+            <template data-htms="head:product_head"></template>
+            </head><body></body></html>"#,
+        );
+
+        assert!(build.task_names().contains("product_head"));
+        assert_eq!(build.task_options()["product_head"].kind, ChunkKind::Head);
+        assert!(!rendered.contains("product_head"));
+        assert!(rendered.contains("<title>Loading...</title>"));
+    }
+
+    #[test]
+    fn fails_on_invalid_head_placeholder() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head><template data-htms="head:product-head"></template></head><body></body></html>"#,
+        );
+
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains("invalid attribute 'template[data-htms]' at byte offset 27"));
+    }
+
+    #[test]
+    fn fails_on_task_bound_to_content_and_head() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head><template data-htms="head:product"></template></head><body>
+            <div data-htms="fn:product"></div>
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains("task 'product' is already a head placeholder"));
+    }
+
+    #[test]
+    fn marks_task_placeholders_as_busy() {
+        let (_, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:news" data-htms-live="polite"></div>
+            <div data-htms="fn:ads" data-htms-busy="false"></div>
+            <div data-htms="fn:stats" aria-busy="false"></div>
+            </body></html>"#,
+        );
+
+        assert!(rendered.contains(r#"<div data-htms="news" aria-busy="true" aria-live="polite">"#));
+        assert!(rendered.contains(r#"<div data-htms="ads">"#));
+        assert!(rendered.contains(r#"<div data-htms="stats" aria-busy="false">"#));
+        assert!(!rendered.contains("data-htms-live"));
+        assert!(!rendered.contains("data-htms-busy"));
+    }
+
+    #[test]
+    fn fails_on_invalid_live_mode() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:news" data-htms-live="loud"></div>
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains("unknown live mode 'loud'"));
+    }
+
+    #[test]
+    fn collects_announcement_and_removes_attribute() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:news" data-htms-announce=" News loaded "></div>
+            </body></html>"#,
+        );
+
+        assert_eq!(
+            build.task_options()["news"].announce.as_deref(),
+            Some("News loaded")
+        );
+        assert!(!rendered.contains("data-htms-announce"));
+    }
+
+    #[test]
+    fn collects_critical_tasks_and_leaves_markers() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:auth" data-htms-await>Checking...</div>
+            <ul data-htms="fn:cart" data-htms-await="true" data-htms-swap="append"><li>first</li></ul>
+            <div data-htms="fn:news"></div>
+            </body></html>"#,
+        );
+        let critical = build.critical_task_names();
+
+        assert!(critical.contains("auth"));
+        assert!(critical.contains("cart"));
+        assert!(!critical.contains("news"));
+        assert!(build.task_names().contains("auth"));
+        assert!(rendered.contains("<body>\n            <!--htms:await:auth-->\n"));
+        assert!(
+            rendered.contains(r#"<ul data-htms="cart"><li>first</li><!--htms:await:cart--></ul>"#)
+        );
+        assert!(rendered.contains(r#"<div data-htms="news" aria-busy="true">"#));
+        assert!(!rendered.contains("data-htms-await"));
+    }
+
+    #[test]
+    fn collects_one_task_per_distinct_task_arguments() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:user_card(42)"></div>
+            <div data-htms="fn:user_card( 7 )"></div>
+            <div data-htms="fn:user_card(42)"></div>
+            <div data-htms="fn:news"></div>
+            </body></html>"#,
+        );
+        let invocations = build.task_invocations();
+        let argument = |value: &str| Argument::Literal {
+            name: "arg0".to_string(),
+            ty: "i64".to_string(),
+            value: value.to_string(),
+        };
+
+        assert_eq!(
+            build.task_names().iter().collect::<Vec<_>>(),
+            ["news", "user_card-0", "user_card-1"]
+        );
+        assert_eq!(invocations["user_card-0"].method, "user_card");
+        assert_eq!(invocations["user_card-0"].arguments, [argument("42")]);
+        assert_eq!(invocations["user_card-1"].arguments, [argument("7")]);
+        assert!(invocations["news"].arguments.is_empty());
+        assert_eq!(
+            build.task_options()["user_card-0"].task.as_deref(),
+            Some("user_card")
+        );
+        assert_eq!(build.task_options()["news"].task, None);
+        assert_eq!(rendered.matches(r#"data-htms="user_card-0""#).count(), 2);
+        assert!(rendered.contains(r#"<div data-htms="user_card-1" aria-busy="true">"#));
+    }
+
+    #[test]
+    fn collects_field_task_arguments() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:news(db, user)"></div>
+            <div data-htms="fn:card(db, 1)"></div>
+            <div data-htms="fn:card(db, 2)"></div>
+            </body></html>"#,
+        );
+        let invocations = build.task_invocations();
+        let field = |name: &str| Argument::Field(name.to_string());
+
+        assert_eq!(
+            build.task_names().iter().collect::<Vec<_>>(),
+            ["card-0", "card-1", "news"]
+        );
+        assert_eq!(invocations["news"].arguments, [field("db"), field("user")]);
+        assert_eq!(invocations["card-1"].arguments[0], field("db"));
+        assert!(invocations["card-1"].arguments[1].is_literal());
+        assert!(rendered.contains(r#"<div data-htms="news" aria-busy="true">"#));
+    }
+
+    #[test]
+    fn collects_typed_named_task_arguments() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:chart" data-htms-arg-kind='"bar"' data-htms-arg-max-value="10u8"
+                 data-htms-arg-ratio="0.5" data-htms-arg-stacked="true"></div>
+            <div data-htms="fn:chart" data-htms-arg-kind="&quot;line&quot;" data-htms-arg-max-value="5u8"
+                 data-htms-arg-ratio="1.5" data-htms-arg-stacked="false"></div>
+            </body></html>"#,
+        );
+        let argument = |name: &str, ty: &str, value: &str| Argument::Literal {
+            name: name.to_string(),
+            ty: ty.to_string(),
+            value: value.to_string(),
+        };
+
+        assert_eq!(
+            build.task_invocations()["chart-1"].arguments,
+            [
+                argument("kind", "&'static str", r#""line""#),
+                argument("max_value", "u8", "5u8"),
+                argument("ratio", "f64", "1.5"),
+                argument("stacked", "bool", "false"),
+            ]
+        );
+        assert!(rendered.contains(r#"<div data-htms="chart-0" aria-busy="true">"#));
+        assert!(!rendered.contains("data-htms-arg-"));
+    }
+
+    #[test]
+    fn fails_on_invalid_task_arguments() {
+        let message = |html: &str| temp_build(html).1.unwrap_err().to_string();
+
+        assert!(
+            message(r#"<p data-htms="fn:card(1)"></p><p data-htms='fn:card("a")'></p>"#)
+                .contains("task 'card' is called with (arg0: i64) and with (arg0: &'static str)")
+        );
+        assert!(
+            message(r#"<p data-htms="fn:card"></p><p data-htms="fn:card(1)"></p>"#)
+                .contains("task 'card' is called with () and with (arg0: i64)")
+        );
+        assert!(
+            message(r#"<p data-htms="fn:card(1)" data-htms-arg-id="1"></p>"#)
+                .contains("arguments are passed either in parentheses or with data-htms-arg-*")
+        );
+        assert!(message(r#"<p data-htms="fn:card(1"></p>"#).contains("missing ')'"));
+        assert!(message(r#"<p data-htms="fn:card(b'x')"></p>"#).contains(
+            "unsupported argument 'arg0', expected a number, a string, a bool or a char literal"
+        ));
+        assert!(
+            message(r#"<p data-htms="fn:card(db user)"></p>"#)
+                .contains("invalid task arguments 'db user'")
+        );
+        assert!(
+            message(r#"<p data-htms="fn:card(db, db)"></p>"#)
+                .contains("field 'db' is passed twice")
+        );
+        assert!(
+            message(r#"<p data-htms="fn:card(db)"></p><p data-htms="fn:card(user)"></p>"#)
+                .contains("task 'card' is called with (db) and with (user)")
+        );
+        assert!(
+            message(r#"<p data-htms="fn:card" data-htms-arg-id="x y"></p>"#)
+                .contains("invalid argument 'id' literal 'x y'")
+        );
+    }
+
+    #[test]
+    fn fails_on_critical_task_with_selector() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:auth" data-htms-await data-htms-selector=".user"></div>
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(
+            message.contains(
+                "critical placeholders cannot use data-htms-selector or data-htms-parent"
+            )
+        );
+    }
+
+    #[test]
+    fn collects_awaited_head_placeholder_and_leaves_marker_in_head() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head><meta charset="utf-8">
+            <template data-htms="head:seo" data-htms-await></template>
+            </head><body></body></html>"#,
+        );
+        let (head_end, _) = split_head(rendered.as_bytes()).expect("head split marker");
+
+        assert!(build.critical_task_names().contains("seo"));
+        assert_eq!(build.task_options()["seo"].kind, ChunkKind::Head);
+        assert!(rendered[..head_end].contains("<!--htms:await:seo-->"));
+        assert!(!rendered.contains("data-htms-await"));
+    }
+
+    #[test]
+    fn fails_on_awaited_head_placeholder_outside_head() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <template data-htms="head:seo" data-htms-await></template></body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains("awaited head placeholders must be in the <head>"));
+    }
+
+    #[test]
+    fn fails_on_task_placed_with_different_options() {
+        let message = |html: &str| temp_build(html).1.unwrap_err().to_string();
+
+        assert!(
+            message(
+                r#"<p data-htms="fn:news" data-htms-swap="inner"></p><p data-htms="fn:news"></p>"#
+            )
+            .contains("task 'news' is placed with different options: 'swap=\"inner\"' and ''")
+        );
+        assert!(
+            message(
+                r#"<p data-htms="fn:news" data-htms-announce="Done"></p>
+                <p data-htms="fn:news" data-htms-announce="Loaded"></p>"#
+            )
+            .contains("task 'news' is placed with different options")
+        );
+    }
+
+    #[test]
+    fn fails_on_task_both_awaited_and_streamed() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:auth" data-htms-await></div>
+            <div data-htms="fn:auth"></div>
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains("task 'auth' is both awaited and streamed"));
+    }
+
+    #[test]
+    fn marks_head_split_after_head_end_tag() {
+        let (_, rendered) = temp_build_with_rendered(
+            "<!doctype html><html><head><title>Title</title></head><body></body></html>",
+        );
+        let (head_end, body_start) = split_head(rendered.as_bytes()).expect("head split");
+
+        assert!(rendered[..head_end].ends_with("</head>"));
+        assert!(rendered[body_start..].starts_with("<body>"));
+    }
+
+    #[test]
+    fn without_head_has_no_split() {
+        let (_, rendered) = temp_build_with_rendered("<p>Content</p>");
+
+        assert_eq!(split_head(rendered.as_bytes()), None);
+    }
+
+    #[test]
+    fn prefixes_ids_with_namespace_marker() {
+        let input_path = write_input_file(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:news"></div>
+            <img data-htms-attr="src:avatar_url alt:avatar_alt">
+            </body></html>"#,
+        );
+        let output_path = unique_path("htms_test_output", "html");
+        let options = Options {
+            namespace: true,
+            ..Options::default()
+        };
+        let build = parse_and_build_with_options(&input_path, &output_path, &options)
+            .expect("parse and build");
+        let rendered = read_output_string(&output_path);
+
+        assert!(build.task_names().contains("news"));
+        assert!(rendered.contains(r#"<div data-htms="%htms:ns%news" aria-busy="true">"#));
+        assert!(
+            rendered.contains(r#"<img data-htms-attr="%htms:ns%avatar_url %htms:ns%avatar_alt">"#)
+        );
+    }
+
+    #[test]
+    fn namespaces_id_target() {
+        let input_path = write_input_file(
+            r#"<!doctype html><html><head></head><body>
+            <section id="latest-news" data-htms="fn:news" data-htms-target="id"></section>
+            </body></html>"#,
+        );
+        let output_path = unique_path("htms_test_output", "html");
+        let options = Options {
+            namespace: true,
+            ..Options::default()
+        };
+        let build = parse_and_build_with_options(&input_path, &output_path, &options)
+            .expect("parse and build");
+        let rendered = read_output_string(&output_path);
+
+        assert_eq!(
+            build.task_options()["news"].element_id.as_deref(),
+            Some("latest-news")
+        );
+        assert!(rendered.contains(r#"<section id="%htms:ns%latest-news" aria-busy="true">"#));
+    }
+
+    #[test]
+    fn collects_id_target_and_removes_placeholder_attribute() {
         let (build, rendered) = temp_build_with_rendered(
             r#"<!doctype html><html><head></head><body>
-            <div data-htms="fn:news"></div>
-            <div data-htms="fn:blog_posts"></div>
+            <section id="latest-news" class="news" data-htms="fn:news" data-htms-target="id"></section>
+            <div data-htms="fn:blog" data-htms-target="data"></div>
             </body></html>"#,
         );
-        let task_names = build.task_names();
+        let task_options = build.task_options();
 
-        assert!(task_names.contains("news"));
-        assert!(task_names.contains("blog_posts"));
-        assert!(rendered.contains(r#"data-htms="news""#));
-        assert!(rendered.contains(r#"data-htms="blog_posts""#));
-        assert!(!rendered.contains(r#"data-htms="fn:news""#));
-        assert!(!rendered.contains(r#"data-htms="fn:blog_posts""#));
+        assert_eq!(
+            task_options["news"].element_id.as_deref(),
+            Some("latest-news")
+        );
+        assert_eq!(task_options["blog"].element_id, None);
+        assert!(build.task_names().contains("news"));
+        assert!(
+            rendered
+                .contains(r#"<section id="latest-news" class="news" aria-busy="true"></section>"#)
+        );
+        assert!(rendered.contains(r#"<div data-htms="blog" aria-busy="true">"#));
+        assert!(!rendered.contains("data-htms-target"));
+    }
+
+    #[test]
+    fn fails_on_id_target_without_id() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:news" data-htms-target="id"></div>
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains("the placeholder has no id"));
+    }
+
+    #[test]
+    fn fails_on_unknown_target() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <div id="news" data-htms="fn:news" data-htms-target="class"></div>
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains("unknown target 'class'"));
+    }
+
+    #[test]
+    fn fails_on_invalid_swap_mode() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="fn:news" data-htms-swap="outer"></div>
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains(
+            r#"invalid attribute 'div[data-htms-swap="outer"]' at byte offset 53: unknown swap mode 'outer'"#
+        ));
     }
 
     #[test]
@@ -420,6 +2935,594 @@ mod parse_and_build {
         assert!(!rendered.contains(r#"data-htms="fn:included_fn""#));
     }
 
+    fn write_include_files(files: &[(&str, &str)]) -> PathBuf {
+        let directory = unique_path("htms_test_includes", "d");
+
+        for (name, contents) in files {
+            let path = directory.join(name);
+            fs::create_dir_all(path.parent().unwrap()).expect("create include directory");
+            fs::write(path, contents).expect("write include file");
+        }
+
+        directory
+    }
+
+    #[test]
+    fn include_nested_fragments_relative_to_the_including_file() {
+        let directory = write_include_files(&[
+            (
+                "index.html",
+                r#"<body><div data-htms="include:partials/card.html"></div></body>"#,
+            ),
+            (
+                "partials/card.html",
+                r#"<section><div data-htms="include:title.html"></div></section>"#,
+            ),
+            (
+                "partials/title.html",
+                r#"<h1 data-htms="fn:title">Title</h1>"#,
+            ),
+        ]);
+        let output_path = unique_path("htms_test_output", "html");
+        let build =
+            parse_and_build(directory.join("index.html"), &output_path).expect("parse and build");
+        let rendered = read_output_string(&output_path);
+
+        assert!(build.task_names().contains("title"));
+        assert!(rendered.contains(r#"<section><h1 data-htms="title""#));
+        assert!(!rendered.contains("include:"));
+    }
+
+    #[test]
+    fn fails_on_include_cycle() {
+        let directory = write_include_files(&[
+            ("index.html", r#"<div data-htms="include:a.html"></div>"#),
+            ("a.html", r#"<p data-htms="include:b.html"></p>"#),
+            ("b.html", r#"<span data-htms="include:a.html"></span>"#),
+        ]);
+        let output_path = unique_path("htms_test_output", "html");
+        let message = parse_and_build(directory.join("index.html"), &output_path)
+            .unwrap_err()
+            .to_string();
+
+        assert!(
+            message
+                .contains(r#"include cycle 'span[data-htms="include:a.html"]' at byte offset 0: "#)
+        );
+        assert!(message.starts_with(&format!(
+            "failed to rewrite the template: {}: ",
+            directory.join("index.html").display()
+        )));
+        assert!(message.contains("a.html -> "));
+        assert!(message.ends_with("a.html"));
+    }
+
+    #[test]
+    fn fails_on_include_deeper_than_max_depth() {
+        let directory = write_include_files(&[
+            ("index.html", r#"<div data-htms="include:a.html"></div>"#),
+            ("a.html", r#"<div data-htms="include:b.html"></div>"#),
+            ("b.html", "<p>Too deep</p>"),
+        ]);
+        let output_path = unique_path("htms_test_output", "html");
+        let options = Options {
+            max_include_depth: 1,
+            ..Options::default()
+        };
+        let message =
+            parse_and_build_with_options(directory.join("index.html"), &output_path, &options)
+                .unwrap_err()
+                .to_string();
+
+        assert!(message.contains(
+            r#"include too deep 'div[data-htms="include:b.html"]' at byte offset 0: more than 1 nested includes"#
+        ));
+    }
+
+    #[test]
+    fn include_absolute_paths_from_the_template_root() {
+        let directory = write_include_files(&[
+            (
+                "pages/index.html",
+                r#"<body><div data-htms="include:/partials/nav.html"></div></body>"#,
+            ),
+            ("partials/nav.html", "<nav>Menu</nav>"),
+        ]);
+        let output_path = unique_path("htms_test_output", "html");
+        let options = Options {
+            root: Some(directory.clone()),
+            ..Options::default()
+        };
+        parse_and_build_with_options(directory.join("pages/index.html"), &output_path, &options)
+            .expect("parse and build");
+        let rendered = read_output_string(&output_path);
+
+        assert!(rendered.contains("<body><nav>Menu</nav>"));
+    }
+
+    #[test]
+    fn include_parent_paths_without_template_root() {
+        let directory = write_include_files(&[
+            (
+                "pages/index.html",
+                r#"<body><div data-htms="include:../partials/nav.html"></div></body>"#,
+            ),
+            ("partials/nav.html", "<nav>Menu</nav>"),
+        ]);
+        let output_path = unique_path("htms_test_output", "html");
+        parse_and_build(directory.join("pages/index.html"), &output_path).expect("parse and build");
+        let rendered = read_output_string(&output_path);
+
+        assert!(rendered.contains("<body><nav>Menu</nav>"));
+    }
+
+    #[test]
+    fn fails_on_include_outside_of_the_template_root() {
+        let directory = write_include_files(&[
+            (
+                "pages/index.html",
+                r#"<div data-htms="include:../secret.txt"></div>"#,
+            ),
+            ("secret.txt", "secret"),
+        ]);
+        let output_path = unique_path("htms_test_output", "html");
+        let options = Options {
+            root: Some(directory.join("pages")),
+            ..Options::default()
+        };
+        let message = parse_and_build_with_options(
+            directory.join("pages/index.html"),
+            &output_path,
+            &options,
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(message.contains(
+            r#"include outside of the template root 'div[data-htms="include:../secret.txt"]' at byte offset 0: "#
+        ));
+        assert!(!read_output_string(&output_path).contains("secret"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fails_on_include_symlink_outside_of_the_template_root() {
+        let directory = write_include_files(&[
+            (
+                "pages/index.html",
+                r#"<div data-htms="include:link.html"></div>"#,
+            ),
+            ("secret.html", "secret"),
+        ]);
+        std::os::unix::fs::symlink(
+            directory.join("secret.html"),
+            directory.join("pages/link.html"),
+        )
+        .expect("create symlink");
+        let output_path = unique_path("htms_test_output", "html");
+        let options = Options {
+            root: Some(directory.join("pages")),
+            ..Options::default()
+        };
+        let message = parse_and_build_with_options(
+            directory.join("pages/index.html"),
+            &output_path,
+            &options,
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(message.contains("include outside of the template root"));
+        assert!(message.contains("secret.html is not in "));
+    }
+
+    #[test]
+    fn fails_on_input_outside_of_the_template_root() {
+        let directory = write_include_files(&[("index.html", "<p>Content</p>")]);
+        let root = write_include_files(&[("other.html", "")]);
+        let output_path = unique_path("htms_test_output", "html");
+        let options = Options {
+            root: Some(root),
+            ..Options::default()
+        };
+        let message =
+            parse_and_build_with_options(directory.join("index.html"), &output_path, &options)
+                .unwrap_err()
+                .to_string();
+
+        assert!(message.contains("input path is outside of the template root: "));
+    }
+
+    #[test]
+    fn fails_on_include_larger_than_max_size() {
+        let directory = write_include_files(&[
+            ("index.html", r#"<div data-htms="include:big.html"></div>"#),
+            ("big.html", "<p>0123456789</p>"),
+        ]);
+        let output_path = unique_path("htms_test_output", "html");
+        let options = Options {
+            max_include_size: 8,
+            ..Options::default()
+        };
+        let message =
+            parse_and_build_with_options(directory.join("index.html"), &output_path, &options)
+                .unwrap_err()
+                .to_string();
+
+        assert!(message.contains(
+            r#"include too large 'div[data-htms="include:big.html"]' at byte offset 0: more than 8 bytes"#
+        ));
+    }
+
+    #[test]
+    fn wrap_content_in_layout_slots() {
+        let directory = write_include_files(&[
+            (
+                "index.html",
+                r#"<div data-htms="wrap:layout.html"><aside slot="sidebar">Menu</aside><p data-htms="fn:news">Loading</p></div>"#,
+            ),
+            (
+                "layout.html",
+                r#"<!doctype html><html><head><slot name="head"><title>Default title</title></slot></head><body><slot name="sidebar"/><main><slot/></main></body></html>"#,
+            ),
+        ]);
+        let output_path = unique_path("htms_test_output", "html");
+        let build =
+            parse_and_build(directory.join("index.html"), &output_path).expect("parse and build");
+        let rendered = read_output_string(&output_path);
+
+        assert!(build.has_html_tag());
+        assert!(build.task_names().contains("news"));
+        assert!(rendered.contains("<title>Default title</title>"));
+        assert!(rendered.contains("<aside>Menu</aside><main><p data-htms=\"news\""));
+        assert!(!rendered.contains("<slot"));
+        assert!(!rendered.contains("wrap:"));
+        assert!(!rendered.contains("<div"));
+    }
+
+    #[test]
+    fn wrap_in_nested_layouts() {
+        let directory = write_include_files(&[
+            (
+                "index.html",
+                r#"<section data-htms="wrap:layouts/page.html"><b slot="title">Home</b>Content</section>"#,
+            ),
+            (
+                "layouts/page.html",
+                r#"<div data-htms="wrap:base.html"><h1><slot name="title"></slot></h1><article><slot/></article></div>"#,
+            ),
+            (
+                "layouts/base.html",
+                "<body><slot></slot><footer>Footer</footer></body>",
+            ),
+        ]);
+        let output_path = unique_path("htms_test_output", "html");
+        parse_and_build(directory.join("index.html"), &output_path).expect("parse and build");
+        let rendered = read_output_string(&output_path);
+
+        assert!(rendered.contains(
+            "<body><h1><b>Home</b></h1><article>Content</article><footer>Footer</footer>"
+        ));
+    }
+
+    #[test]
+    fn fails_on_wrap_cycle() {
+        let directory = write_include_files(&[
+            ("index.html", r#"<div data-htms="wrap:layout.html"></div>"#),
+            (
+                "layout.html",
+                r#"<div data-htms="wrap:index.html"><slot/></div>"#,
+            ),
+        ]);
+        let output_path = unique_path("htms_test_output", "html");
+        let message = parse_and_build(directory.join("index.html"), &output_path)
+            .unwrap_err()
+            .to_string();
+
+        assert!(
+            message
+                .contains(r#"include cycle 'div[data-htms="wrap:index.html"]' at byte offset 0: "#)
+        );
+    }
+
+    #[test]
+    fn include_component_with_props() {
+        let directory = write_include_files(&[
+            (
+                "index.html",
+                r#"<body>
+                <div data-htms="include:card.html" data-htms-prop-title="Revenue &amp; costs" data-htms-prop-task="revenue"></div>
+                <div data-htms="include:card.html" data-htms-prop-title="<Users>" data-htms-prop-task="users"></div>
+                </body>"#,
+            ),
+            (
+                "card.html",
+                r#"<article title="{{ title }}"><h2>{{title}}</h2><div data-htms="fn:{{task}}">{{ unknown }}</div></article>"#,
+            ),
+        ]);
+        let output_path = unique_path("htms_test_output", "html");
+        let build =
+            parse_and_build(directory.join("index.html"), &output_path).expect("parse and build");
+        let rendered = read_output_string(&output_path);
+
+        assert!(build.task_names().contains("revenue"));
+        assert!(build.task_names().contains("users"));
+        assert!(
+            rendered
+                .contains(r#"<article title="Revenue &amp; costs"><h2>Revenue &amp; costs</h2>"#)
+        );
+        assert!(rendered.contains(r#"<article title="&lt;Users&gt;"><h2>&lt;Users&gt;</h2>"#));
+        assert!(rendered.contains(r#"data-htms="revenue""#));
+        assert!(rendered.contains("{{ unknown }}"));
+    }
+
+    #[test]
+    fn include_components_with_scoped_styles() {
+        let directory = write_include_files(&[
+            (
+                "index.html",
+                r#"<body><p class="title">Page</p><div data-htms="include:card.html"></div><div data-htms="include:chart.html"></div></body>"#,
+            ),
+            (
+                "card.html",
+                r#"<style data-htms-scoped>.title { color: red }</style><h2 class="title">Card</h2>"#,
+            ),
+            (
+                "chart.html",
+                r#"<style data-htms-scoped>.title::after { content: "!" }</style><h2 class="title">Chart</h2>"#,
+            ),
+        ]);
+        let output_path = unique_path("htms_test_output", "html");
+        parse_and_build(directory.join("index.html"), &output_path).expect("parse and build");
+        let rendered = read_output_string(&output_path);
+        let card = scope_attribute("card.html");
+        let chart = scope_attribute("chart.html");
+
+        assert!(rendered.contains(r#"<p class="title">Page</p>"#));
+        assert!(rendered.contains(&format!(
+            r#"<style>.title[{card}] {{ color: red }}</style><h2 class="title" {card}="">Card</h2>"#
+        )));
+        assert!(rendered.contains(&format!(
+            r#"<style>.title[{chart}]::after {{ content: "!" }}</style><h2 class="title" {chart}="">Chart</h2>"#
+        )));
+    }
+
+    #[test]
+    fn replace_context_bindings_with_markers() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head><title data-htms="text:title">Title</title></head><body>
+            <a data-htms-attr-href="link" data-htms-attr-title="user.name" class="user">Profile</a>
+            </body></html>"#,
+        );
+
+        assert_eq!(
+            build.bindings().iter().collect::<Vec<_>>(),
+            ["link", "title", "user.name"]
+        );
+        assert!(rendered.contains("<title>%htms:bind:title%</title>"));
+        assert!(rendered.contains(
+            r#"<a class="user" href="%htms:bind:link%" title="%htms:bind:user.name%">Profile</a>"#
+        ));
+        assert!(!rendered.contains("data-htms-attr-"));
+        assert!(!rendered.contains("text:"));
+    }
+
+    #[test]
+    fn fails_on_invalid_binding_path() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <a data-htms-attr-href="user.home-page"></a>
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains(
+            r#"invalid attribute 'a[data-htms-attr-href="user.home-page"]' at byte offset 53: invalid binding path segment 'home-page'"#
+        ));
+    }
+
+    #[test]
+    fn surround_conditional_sections_with_markers() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head></head><body>
+            <nav data-htms="if:user.is_admin"><a href="/admin">Admin</a></nav>
+            <p data-htms="unless:user.is_admin">Welcome</p><br data-htms="if:wide">
+            </body></html>"#,
+        );
+
+        assert_eq!(
+            build.conditions().iter().collect::<Vec<_>>(),
+            ["user.is_admin", "wide"]
+        );
+        assert!(
+            rendered.contains(
+                r#"%htms:if:user.is_admin%<nav><a href="/admin">Admin</a></nav>%htms:end%"#
+            )
+        );
+        assert!(rendered.contains(r"%htms:unless:user.is_admin%<p>Welcome</p>%htms:end%"));
+        assert!(rendered.contains(r"%htms:if:wide%<br>%htms:end%"));
+        assert!(!rendered.contains(r#"data-htms="if:"#));
+    }
+
+    #[test]
+    fn collect_guards_of_task_placeholders() {
+        let (build, _) = temp_build_with_rendered(
+            r#"<!doctype html><html><head></head><body>
+            <section data-htms="if:user">
+              <div data-htms="fn:profile"></div>
+              <ul data-htms="unless:user.is_admin"><li data-htms="fn:news"></li></ul>
+              <br data-htms="if:wide"><div data-htms="fn:stats"></div>
+            </section>
+            <div data-htms="fn:stats"></div>
+            <div data-htms="fn:footer"></div>
+            <img data-htms="if:user" data-htms-attr="src:avatar">
+            </body></html>"#,
+        );
+        let guard = |path: &str, negated| Guard {
+            path: path.to_string(),
+            negated,
+        };
+        let guards = build.task_guards();
+
+        assert_eq!(guards["profile"], [vec![guard("user", false)]]);
+        assert_eq!(
+            guards["news"],
+            [vec![guard("user", false), guard("user.is_admin", true)]]
+        );
+        assert_eq!(guards["stats"], [vec![guard("user", false)], vec![]]);
+        assert_eq!(guards["footer"], [Vec::<Guard>::new()]);
+        assert_eq!(guards["avatar"], [vec![guard("user", false)]]);
+    }
+
+    #[test]
+    fn surround_repeated_sections_with_markers_and_collect_item_paths() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head></head><body>
+            <ul><li data-htms="each:link in menu.links" data-htms-attr-class="link.class">
+              <a data-htms-attr-href="link.href" data-htms="text:link.name"></a>
+              <b data-htms="if:link.active">*</b><i data-htms="text:user.name"></i>
+            </li></ul>
+            <p data-htms="each:tags"><span data-htms="text:item"></span></p>
+            </body></html>"#,
+        );
+        let collections = build.collections();
+        let links = &collections["menu.links"];
+
+        assert_eq!(links.item, "link");
+        assert_eq!(
+            links.bindings.iter().collect::<Vec<_>>(),
+            ["link.class", "link.href", "link.name"]
+        );
+        assert_eq!(links.conditions.iter().collect::<Vec<_>>(), ["link.active"]);
+        assert_eq!(collections["tags"].item, "item");
+        assert_eq!(
+            collections["tags"].bindings.iter().collect::<Vec<_>>(),
+            ["item"]
+        );
+        assert_eq!(build.bindings().iter().collect::<Vec<_>>(), ["user.name"]);
+        assert!(build.conditions().is_empty());
+        assert!(
+            rendered.contains(r#"<ul>%htms:each:menu.links%<li class="%htms:bind:link.class%">"#)
+        );
+        assert!(rendered.contains("</li>%htms:end%</ul>"));
+        assert!(
+            rendered.contains("%htms:each:tags%<p><span>%htms:bind:item%</span></p>%htms:end%")
+        );
+    }
+
+    #[test]
+    fn fails_on_task_placeholder_in_repeated_section() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <ul data-htms="each:items"><li data-htms="fn:details"></li></ul>
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains(
+            r#"invalid attribute 'li[data-htms="fn:details"]' at byte offset 80: task placeholders cannot be repeated by 'each:items'"#
+        ));
+    }
+
+    #[test]
+    fn fails_on_reserved_marker_in_template() {
+        let message = |html: &str| temp_build(html).1.unwrap_err().to_string();
+
+        assert!(
+            message("<p>50%htms:if:x% off</p>")
+                .contains("reserved text '%htms:' in the template at byte offset 5")
+        );
+        assert!(
+            message(r#"<a title="%htms:bind:x%"></a>"#)
+                .contains("reserved text '%htms:' in the template at byte offset 10")
+        );
+        assert!(temp_build("<p>50% off, 100%htms</p>").1.is_ok());
+    }
+
+    #[test]
+    fn fails_on_section_enclosing_head_end() {
+        let message = |html: &str| temp_build(html).1.unwrap_err().to_string();
+
+        assert!(
+            message(
+                r#"<!doctype html><html data-htms="if:x"><head></head><body></body></html>"#
+            )
+            .contains(
+                r#"invalid attribute 'html[data-htms="if:x"]' at byte offset 15: sections cannot enclose the </head>"#
+            )
+        );
+        assert!(
+            message(
+                r#"<!doctype html><html><head data-htms="each:metas"></head><body></body></html>"#
+            )
+            .contains(r#"invalid attribute 'head[data-htms="each:metas"]' at byte offset 21"#)
+        );
+    }
+
+    #[test]
+    fn fails_on_nested_or_inconsistent_repeated_sections() {
+        let (_, nested) =
+            temp_build(r#"<div data-htms="each:rows"><p data-htms="each:cells"></p></div>"#);
+        let (_, renamed) =
+            temp_build(r#"<p data-htms="each:rows"></p><p data-htms="each:row in rows"></p>"#);
+        let (_, invalid) = temp_build(r#"<p data-htms="each:a-b in rows"></p>"#);
+
+        assert!(nested.unwrap_err().to_string().contains(
+            r#"invalid attribute 'p[data-htms="each:cells"]' at byte offset 27: each: sections cannot be nested"#
+        ));
+        assert!(renamed.unwrap_err().to_string().contains(
+            r#"invalid attribute 'p[data-htms="each:row in rows"]' at byte offset 29: collection 'rows' is already iterated as 'item'"#
+        ));
+        assert!(
+            invalid
+                .unwrap_err()
+                .to_string()
+                .contains("invalid item name 'a-b'")
+        );
+    }
+
+    #[test]
+    fn fails_on_invalid_condition_path() {
+        let (_, build) = temp_build(
+            r#"<!doctype html><html><head></head><body>
+            <div data-htms="if:user."></div>
+            </body></html>"#,
+        );
+        let message = build.unwrap_err().to_string();
+
+        assert!(message.contains(
+            r#"invalid attribute 'div[data-htms="if:user."]' at byte offset 53: invalid binding path segment ''"#
+        ));
+    }
+
+    #[test]
+    fn split_compiled_template_into_head_and_template_segments() {
+        let (build, rendered) = temp_build_with_rendered(
+            r#"<!doctype html><html><head><title data-htms="text:title"></title></head><body>
+            <p data-htms="text:user.name"></p>
+            </body></html>"#,
+        );
+        let head = build.head_segments().expect("head segments");
+        let template = build.template_segments();
+
+        assert_eq!(build.compiled(), rendered.as_bytes());
+        assert_eq!(head[1], Segment::Binding("title"));
+        assert!(matches!(head[2], Segment::Static(bytes) if bytes.ends_with(b"</head>")));
+        assert_eq!(template[1], Segment::Binding("user.name"));
+        assert!(matches!(template[0], Segment::Static(bytes) if bytes.starts_with(b"<body>")));
+    }
+
+    #[test]
+    fn without_head_has_only_template_segments() {
+        let (build, _) = temp_build_with_rendered("<p>Content</p>");
+
+        assert!(build.head_segments().is_none());
+        assert_eq!(
+            build.template_segments(),
+            [Segment::Static(b"<p>Content</p>")]
+        );
+    }
+
     #[test]
     fn fails_on_invalid_htms_attribute() {
         let (_, build) = temp_build(
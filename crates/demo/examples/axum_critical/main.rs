@@ -0,0 +1,36 @@
+use axum::{
+    Router,
+    extract::Path,
+    response::{IntoResponse, Response},
+    routing::get,
+    serve,
+};
+use color_eyre::eyre::Result;
+use htms::Render;
+use tokio::net;
+
+use crate::index::AxumCriticalExample;
+
+#[path = "pages/index.rs"]
+mod index;
+
+async fn handler(Path(product_id): Path<u32>) -> Response {
+    AxumCriticalExample {
+        context: product_id,
+    }
+    .render_with_parts()
+    .await
+    .into_response()
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let app = Router::new().route("/products/{product_id}", get(handler));
+    let listener = net::TcpListener::bind("127.0.0.1:3000").await?;
+
+    println!("listening on {}", listener.local_addr()?);
+    println!("try /products/42 and /products/7");
+    serve(listener, app).await?;
+
+    Ok(())
+}
@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+use htms::{Template, task::Output};
+use tokio::time::sleep;
+
+#[derive(Template, Debug)]
+#[template = "examples/axum_critical/pages/index.html"]
+pub struct AxumCriticalExample {
+    pub context: u32,
+}
+
+impl AxumCriticalExampleRender for AxumCriticalExample {
+    async fn product_task(product_id: u32) -> Output {
+        sleep(Duration::from_millis(500)).await;
+
+        if product_id == 42 {
+            Output::new("<h1>The answer</h1><p>Product #42 is in stock.</p>")
+        } else {
+            Output::new(format!("<h1>Product #{product_id} not found</h1>"))
+                .with_status(404)
+                .with_header("cache-control", "no-store")
+        }
+    }
+
+    async fn reviews_task(product_id: u32) -> String {
+        sleep(Duration::from_millis(2000)).await;
+        format!("<p>No reviews for product #{product_id} yet.</p>")
+    }
+}
@@ -0,0 +1,36 @@
+use axum::{
+    Router,
+    response::{IntoResponse, Response},
+    routing::get,
+    serve,
+};
+use color_eyre::eyre::Result;
+use htms::{
+    Render,
+    axum::{HtmlStream, assets_router},
+};
+use tokio::net;
+
+use crate::index::AxumExternalAssetsExample;
+
+#[path = "pages/index.rs"]
+mod index;
+
+async fn handler() -> Response {
+    let stream = AxumExternalAssetsExample::default().render();
+
+    HtmlStream::from(stream).into_response()
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let app = Router::new()
+        .route("/", get(handler))
+        .merge(assets_router());
+    let listener = net::TcpListener::bind("127.0.0.1:3000").await?;
+
+    println!("listening on {}", listener.local_addr()?);
+    serve(listener, app).await?;
+
+    Ok(())
+}
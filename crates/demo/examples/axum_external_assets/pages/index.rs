@@ -0,0 +1,21 @@
+use std::time::Duration;
+
+use htms::Template;
+use tokio::time::sleep;
+
+#[derive(Template, Debug, Default)]
+#[template = "examples/axum_external_assets/pages/index.html"]
+#[htms(assets = "external")]
+pub struct AxumExternalAssetsExample {}
+
+impl AxumExternalAssetsExampleRender for AxumExternalAssetsExample {
+    async fn blog_posts_task() -> String {
+        sleep(Duration::from_millis(2000)).await;
+        "<p>Some blog posts here :)</p>".to_string()
+    }
+
+    async fn news_task() -> String {
+        sleep(Duration::from_millis(1000)).await;
+        "<p>Some news here :)</p>".to_string()
+    }
+}
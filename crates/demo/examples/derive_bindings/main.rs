@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use htms::Template;
+use tokio::{
+    io::{AsyncWriteExt, stdout},
+    time::sleep,
+};
+
+#[derive(Debug, Clone)]
+struct User {
+    name: String,
+    profile_url: String,
+}
+
+#[derive(Debug, Clone)]
+struct Context {
+    title: String,
+    user: User,
+}
+
+#[derive(Template, Debug)]
+#[template = "examples/derive_bindings/index.html"]
+struct DeriveBindingsExample {
+    context: Context,
+}
+
+impl DeriveBindingsExampleRender for DeriveBindingsExample {
+    async fn activity_task(context: Context) -> String {
+        sleep(Duration::from_millis(1000)).await;
+        format!("<p>{}: 3 new discussions today.</p>", context.title)
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let mut stdout = stdout();
+    let example = DeriveBindingsExample {
+        context: Context {
+            title: "Profile of Ada".to_string(),
+            // bound values are escaped into the template
+            user: User {
+                name: "Ada <Lovelace> & co".to_string(),
+                profile_url: "/users?id=1&tab=bio".to_string(),
+            },
+        },
+    };
+    let mut stream = Box::pin(example.render());
+
+    while let Some(bytes) = stream.next().await {
+        stdout.write_all(&bytes).await.unwrap();
+        stdout.flush().await.unwrap();
+    }
+}
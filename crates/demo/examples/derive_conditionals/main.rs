@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use htms::Template;
+use tokio::{
+    io::{AsyncWriteExt, stdout},
+    time::sleep,
+};
+
+#[derive(Debug, Clone)]
+struct User {
+    name: String,
+}
+
+#[derive(Debug, Clone)]
+struct Context {
+    show_news: bool,
+    user: Option<User>,
+}
+
+#[derive(Template, Debug)]
+#[template = "examples/derive_conditionals/index.html"]
+struct DeriveConditionalsExample {
+    context: Context,
+}
+
+impl DeriveConditionalsExampleRender for DeriveConditionalsExample {
+    // not run when `show_news` is false, its section is omitted
+    async fn news_task(context: Context) -> String {
+        sleep(Duration::from_millis(1000)).await;
+        let reader = context
+            .user
+            .map_or_else(|| "guest".to_string(), |user| user.name);
+        format!("<p>Nothing new today for {reader}.</p>")
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let mut stdout = stdout();
+    let example = DeriveConditionalsExample {
+        context: Context {
+            show_news: true,
+            user: None,
+        },
+    };
+    let mut stream = Box::pin(example.render());
+
+    while let Some(bytes) = stream.next().await {
+        stdout.write_all(&bytes).await.unwrap();
+        stdout.flush().await.unwrap();
+    }
+}
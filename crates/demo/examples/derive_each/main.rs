@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use htms::Template;
+use tokio::{
+    io::{AsyncWriteExt, stdout},
+    time::sleep,
+};
+
+#[derive(Debug, Clone)]
+struct Link {
+    name: String,
+    href: String,
+    active: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Context {
+    links: Vec<Link>,
+    tags: Vec<String>,
+}
+
+#[derive(Template, Debug)]
+#[template = "examples/derive_each/index.html"]
+struct DeriveEachExample {
+    context: Context,
+}
+
+impl DeriveEachExampleRender for DeriveEachExample {
+    async fn page_task(context: Context) -> String {
+        sleep(Duration::from_millis(1000)).await;
+        let current = context.links.iter().find(|link| link.active);
+        format!("<h1>{}</h1>", current.map_or("Docs", |link| &link.name))
+    }
+}
+
+fn link(name: &str, href: &str, active: bool) -> Link {
+    Link {
+        name: name.to_string(),
+        href: href.to_string(),
+        active,
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let mut stdout = stdout();
+    let example = DeriveEachExample {
+        context: Context {
+            links: vec![
+                link("Getting started", "/docs/start", false),
+                link("Templates", "/docs/templates", true),
+                link("Tasks & chunks", "/docs/tasks", false),
+            ],
+            tags: vec!["rust".to_string(), "streaming".to_string()],
+        },
+    };
+    let mut stream = Box::pin(example.render());
+
+    while let Some(bytes) = stream.next().await {
+        stdout.write_all(&bytes).await.unwrap();
+        stdout.flush().await.unwrap();
+    }
+}
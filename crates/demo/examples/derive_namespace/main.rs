@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use htms::Template;
+use tokio::{
+    io::{AsyncWriteExt, stdout},
+    time::sleep,
+};
+
+#[derive(Debug, Clone)]
+struct Context {
+    title: &'static str,
+}
+
+// Each instance prefixes its chunk ids (`Card-0-stats`, `Card-1-stats`, ...), so the card can
+// appear several times in a page.
+#[derive(Template, Debug)]
+#[template = "examples/derive_namespace/card.html"]
+#[htms(namespace)]
+struct Card {
+    context: Context,
+}
+
+impl CardRender for Card {
+    async fn stats_task(context: Context) -> String {
+        sleep(Duration::from_millis(500)).await;
+        format!("<p>Stats of {}</p>", context.title)
+    }
+
+    async fn updated_task(context: Context) -> String {
+        sleep(Duration::from_millis(1000)).await;
+        format!("<p>{} updated just now</p>", context.title)
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let mut stdout = stdout();
+
+    for title in ["Sales", "Traffic"] {
+        let card = Card {
+            context: Context { title },
+        };
+        let mut stream = Box::pin(card.render());
+
+        while let Some(bytes) = stream.next().await {
+            stdout.write_all(&bytes).await.unwrap();
+            stdout.flush().await.unwrap();
+        }
+    }
+}
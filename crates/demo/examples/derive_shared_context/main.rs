@@ -0,0 +1,56 @@
+use std::{sync::Arc, time::Duration};
+
+use futures_util::StreamExt;
+use htms::Template;
+use tokio::{
+    io::{AsyncWriteExt, stdout},
+    time::sleep,
+};
+
+// large and not `Clone`, shared by the tasks instead of cloned for each of them
+#[derive(Debug)]
+struct Context {
+    title: String,
+    samples: Vec<u64>,
+}
+
+#[derive(Template, Debug)]
+#[template = "examples/derive_shared_context/index.html"]
+#[htms(shared_context)]
+struct DeriveSharedContextExample {
+    context: Context,
+}
+
+impl DeriveSharedContextExampleRender for DeriveSharedContextExample {
+    async fn summary_task(context: Arc<Context>) -> String {
+        sleep(Duration::from_millis(500)).await;
+        let total: u64 = context.samples.iter().sum();
+        format!(
+            "<p>{} samples, {total} in total.</p>",
+            context.samples.len()
+        )
+    }
+
+    async fn details_task(context: Arc<Context>) -> String {
+        sleep(Duration::from_millis(1000)).await;
+        let max = context.samples.iter().max().copied().unwrap_or_default();
+        format!("<p>Largest sample: {max}.</p>")
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let mut stdout = stdout();
+    let example = DeriveSharedContextExample {
+        context: Context {
+            title: "Weekly report".to_string(),
+            samples: (1..=100_000).collect(),
+        },
+    };
+    let mut stream = Box::pin(example.render());
+
+    while let Some(bytes) = stream.next().await {
+        stdout.write_all(&bytes).await.unwrap();
+        stdout.flush().await.unwrap();
+    }
+}
@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use htms::Template;
+use tokio::{
+    io::{AsyncWriteExt, stdout},
+    time::sleep,
+};
+
+#[derive(Template, Debug)]
+#[template = "examples/derive_task_arguments/index.html"]
+struct DeriveTaskArgumentsExample {}
+
+impl DeriveTaskArgumentsExampleRender for DeriveTaskArgumentsExample {
+    // runs once for `author(1)` and once for `author(2)`
+    async fn author_task(arg0: i64) -> String {
+        sleep(Duration::from_millis(500 * arg0.unsigned_abs())).await;
+        format!("<article><h2>Author #{arg0}</h2></article>")
+    }
+
+    async fn books_task(author: i64, limit: u8) -> String {
+        sleep(Duration::from_millis(1500)).await;
+        let books: String = (1..=limit)
+            .map(|book| format!("<li>Book {book} of author #{author}</li>"))
+            .collect();
+        format!("<aside><ul>{books}</ul></aside>")
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let mut stdout = stdout();
+    let example = DeriveTaskArgumentsExample {};
+    let mut stream = Box::pin(example.render());
+
+    while let Some(bytes) = stream.next().await {
+        stdout.write_all(&bytes).await.unwrap();
+        stdout.flush().await.unwrap();
+    }
+}
@@ -0,0 +1,63 @@
+use std::{sync::Arc, time::Duration};
+
+use futures_util::StreamExt;
+use htms::Template;
+use tokio::{
+    io::{AsyncWriteExt, stdout},
+    time::sleep,
+};
+
+// cheap to clone handle, as a connection pool would be
+#[derive(Debug, Clone)]
+struct Db {
+    headlines: Arc<Vec<&'static str>>,
+}
+
+impl Db {
+    async fn headlines(&self) -> Vec<&'static str> {
+        sleep(Duration::from_millis(1000)).await;
+        self.headlines.to_vec()
+    }
+}
+
+#[derive(Template, Debug)]
+#[template = "examples/derive_task_fields/index.html"]
+struct DeriveTaskFieldsExample {
+    db: Db,
+    locale: &'static str,
+}
+
+impl DeriveTaskFieldsExampleRender for DeriveTaskFieldsExample {
+    // each task takes the fields it names, in order, instead of the context
+    async fn greeting_task(locale: &'static str) -> String {
+        let greeting = if locale == "fr" { "Bonjour" } else { "Hello" };
+        format!("<header><p>{greeting}</p></header>")
+    }
+
+    async fn news_task(db: Db, locale: &'static str) -> String {
+        let items: String = db
+            .headlines()
+            .await
+            .iter()
+            .map(|headline| format!("<li lang=\"{locale}\">{headline}</li>"))
+            .collect();
+        format!("<section><ul>{items}</ul></section>")
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let mut stdout = stdout();
+    let example = DeriveTaskFieldsExample {
+        db: Db {
+            headlines: Arc::new(vec!["Nouvelle version publiée", "Appel à contributions"]),
+        },
+        locale: "fr",
+    };
+    let mut stream = Box::pin(example.render());
+
+    while let Some(bytes) = stream.next().await {
+        stdout.write_all(&bytes).await.unwrap();
+        stdout.flush().await.unwrap();
+    }
+}
@@ -1,12 +1,25 @@
-use std::{env, env::VarError, path::PathBuf, result};
-
-use darling::{FromDeriveInput, FromField, ast::Data};
-use htms_core::template;
+use std::{
+    collections::BTreeMap,
+    env,
+    env::VarError,
+    path::{Path, PathBuf},
+    result,
+};
+
+use darling::{FromDeriveInput, FromField, ast::Data, util::Flag};
+use htms_core::{
+    assets::AssetMode,
+    chunk::{ChunkKind, ChunkOptions, Swap},
+    segment::Segment,
+    template,
+};
 use proc_macro::TokenStream;
-use proc_macro2::Literal;
-use quote::{ToTokens, format_ident, quote};
+use proc_macro2::{Literal, TokenStream as TokenStream2};
+use quote::{ToTokens, format_ident, quote, quote_spanned};
 use syn::{Attribute, DeriveInput, Expr, ExprLit, Ident, ItemStruct, Lit, LitStr, Type};
 
+const TEMPLATE_ROOT_VAR: &str = "HTMS_TEMPLATE_ROOT";
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error(transparent)]
@@ -41,11 +54,16 @@ struct TemplateField {
 }
 
 #[derive(Debug, FromDeriveInput)]
-#[darling(attributes(context), forward_attrs(template), supports(struct_named))]
+#[darling(attributes(htms), forward_attrs(template), supports(struct_named))]
 struct TemplateInput {
     ident: Ident,
     data: Data<(), TemplateField>,
     attrs: Vec<Attribute>,
+    assets: Option<LitStr>,
+    namespace: Flag,
+    shared_context: Flag,
+    max_include_depth: Option<usize>,
+    max_include_size: Option<u64>,
 }
 
 pub fn template(input: &DeriveInput) -> Result<TokenStream> {
@@ -53,16 +71,27 @@ pub fn template(input: &DeriveInput) -> Result<TokenStream> {
     let template_path_lit = get_template_path_lit(&template_input)?;
     let context_field = find_context_field(&template_input)?;
 
+    if context_field.is_none() && template_input.shared_context.is_present() {
+        return Err(Error::Syn(syn::Error::new(
+            template_input.shared_context.span(),
+            "#[htms(shared_context)] requires a context field",
+        )));
+    }
     // TODO: allow to override the build path by env var
     let crate_path = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
     let build_path = crate_path.join(".htms").join("build");
+    let root_path = get_template_root(&crate_path)?;
+    let options = get_template_options(&template_input)?.with_root(&root_path);
 
-    let template_path_input = crate_path.join(template_path_lit.value());
+    let template_path_input = root_path.join(template_path_lit.value());
     let template_path_output = build_path.join(template_path_lit.value());
-    let template_path_output_lit = Literal::string(&template_path_output.to_string_lossy());
 
-    let build = template::parse_and_build(&template_path_input, &template_path_output)
-        .map_err(|error| Error::Syn(syn::Error::new_spanned(&template_path_lit, error)))?;
+    let build = template::parse_and_build_with_options(
+        &template_path_input,
+        &template_path_output,
+        &options,
+    )
+    .map_err(|error| Error::Syn(syn::Error::new_spanned(&template_path_lit, error)))?;
 
     let input_struct: ItemStruct = syn::parse(input.to_token_stream().into())?;
     let (impl_generics, ty_generics, where_clause) = input_struct.generics.split_for_impl();
@@ -70,44 +99,39 @@ pub fn template(input: &DeriveInput) -> Result<TokenStream> {
     let input_struct_ident = format_ident!("{}", input_struct.ident);
     let input_trait_ident = format_ident!("{}Render", input_struct.ident);
 
-    let (task_names, method_idents) = build
-        .task_names()
-        .iter()
-        .map(|name| (name.as_str(), format_ident!("{}_task", name)))
-        .collect::<(Vec<_>, Vec<_>)>();
-
-    let (task_arguments, context_field) = match context_field {
-        Some(context) => {
-            let ty = context.ty;
-            let ident = context.ident;
-            (quote! { context: #ty }, quote! { self.#ident.clone() })
-        },
-        None => (quote! {}, quote! {}),
-    };
+    let context_methods =
+        context_methods_tokens(&build, context_field.as_ref(), &template_path_lit)?;
+
+    let fields = get_template_fields(&template_input.data);
+
+    let tasks_method = tasks_tokens(&build, context_field.as_ref(), fields, &template_path_lit)?;
+
+    let trait_methods =
+        trait_methods_tokens(&build, context_field.as_ref(), fields, &template_path_lit)?;
 
     let base_trait = quote! {
         pub trait #input_trait_ident {
-            #(fn #method_idents(#task_arguments) -> impl ::core::future::Future<Output = ::std::string::String> + Send + 'static;)*
+            #(#trait_methods)*
         }
     };
 
-    let final_chunk_body = if build.has_html_tag() {
-        quote! { Some(::htms::Bytes::from_static(br#"<script class="htms-dirty">htmsCleanup();</script></body></html>"#)) }
-    } else {
-        quote! { None }
-    };
+    let final_chunk_body = final_chunk_tokens(build.has_html_tag(), options.assets);
+
+    let head_and_template = template_tokens(&build);
+
+    let namespace_method = namespace_tokens(options.namespace, &input_struct_ident);
 
     let render_impl = quote! {
          use ::htms::Render;
 
          impl #impl_generics ::htms::Render for #input_struct_ident #ty_generics #where_clause {
-            fn tasks(self) -> Option<Vec<::htms::Task>> {
-                Some(vec![#(::htms::Task::new(#task_names, Self::#method_idents(#context_field)),)*])
-            }
+            #tasks_method
 
-            fn template() -> ::htms::Bytes {
-                ::htms::Bytes::from_static(include_bytes!(#template_path_output_lit))
-            }
+            #head_and_template
+
+            #namespace_method
+
+            #context_methods
 
             fn final_chunk() -> Option<::htms::Bytes> {
                 #final_chunk_body
@@ -116,16 +140,611 @@ pub fn template(input: &DeriveInput) -> Result<TokenStream> {
     };
 
     Ok(quote! {
+        // the template root is read from the environment, rebuild when it changes
+        const _: ::core::option::Option<&str> = ::core::option_env!(#TEMPLATE_ROOT_VAR);
+
         #base_trait
         #render_impl
     }
     .into())
 }
 
+/// `Render::tasks`, a `Task` per invocation whose placeholders are in rendered sections.
+fn tasks_tokens(
+    build: &template::Build,
+    context_field: Option<&ContextField>,
+    fields: &[TemplateField],
+    template_path_lit: &LitStr,
+) -> Result<TokenStream2> {
+    let context_value = context_field.map(ContextField::task_value);
+    let mut tasks = Vec::with_capacity(build.task_names().len());
+
+    for name in build.task_names() {
+        let guard = task_guard_tokens(
+            &build.task_guards()[name],
+            context_value.as_ref(),
+            template_path_lit,
+        );
+        let call = task_call_tokens(
+            &build.task_invocations()[name],
+            context_field,
+            fields,
+            template_path_lit,
+        )?;
+        let options = chunk_options_tokens(&build.task_options()[name]);
+        let constructor = if build.critical_task_names().contains(name) {
+            quote! { critical }
+        } else {
+            quote! { new }
+        };
+
+        tasks.push(quote! {
+            if #guard {
+                tasks.push(::htms::Task::#constructor(#name, #call).with_options(#options));
+            }
+        });
+    }
+
+    let shared_context = context_field
+        .filter(|context| context.shared && uses_context(build, context))
+        .map(|context| {
+            let ident = &context.ident;
+            quote! { let context = ::std::sync::Arc::new(self.#ident); }
+        });
+
+    Ok(quote! {
+        fn tasks(self) -> Option<Vec<::htms::Task>> {
+            let mut tasks = ::std::vec::Vec::new();
+            #shared_context
+
+            #(#tasks)*
+
+            Some(tasks)
+        }
+    })
+}
+
+/// One trait method per task method, with the context (unless it takes struct fields) and the
+/// typed arguments of its invocations as parameters.
+fn trait_methods_tokens(
+    build: &template::Build,
+    context_field: Option<&ContextField>,
+    fields: &[TemplateField],
+    template_path_lit: &LitStr,
+) -> Result<Vec<TokenStream2>> {
+    let mut methods = BTreeMap::new();
+
+    for (name, invocation) in build.task_invocations() {
+        let critical = build.critical_task_names().contains(name);
+
+        methods
+            .entry(invocation.method.as_str())
+            .or_insert((invocation, critical));
+    }
+
+    methods
+        .into_values()
+        .map(|(invocation, critical)| {
+            let method_ident = format_ident!("{}_task", invocation.method);
+            let output = if critical {
+                quote! { ::htms::task::Output }
+            } else {
+                quote! { ::std::string::String }
+            };
+            let parameters = invocation
+                .arguments
+                .iter()
+                .map(|argument| match argument {
+                    template::Argument::Literal { name, ty, .. } => {
+                        let name = format_ident!("{name}");
+                        let ty = syn::parse_str::<Type>(ty)
+                            .map_err(|error| syn::Error::new_spanned(template_path_lit, error))?;
+
+                        Ok(quote! { #name: #ty })
+                    },
+                    template::Argument::Field(field) => {
+                        let name = format_ident!("{field}");
+                        let ty = match context_field {
+                            Some(context) if context.ident == field => context.task_type(),
+                            _ => find_task_field(fields, field, invocation, template_path_lit)?
+                                .ty
+                                .to_token_stream(),
+                        };
+
+                        Ok(quote! { #name: #ty })
+                    },
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let context_parameter = context_field
+                .filter(|_| !takes_fields(invocation))
+                .map(|context| {
+                    let ty = context.task_type();
+                    quote! { context: #ty }
+                });
+            let parameters = context_parameter.into_iter().chain(parameters);
+
+            Ok(quote! {
+                fn #method_ident(#(#parameters),*) -> impl ::core::future::Future<Output = #output> + Send + 'static;
+            })
+        })
+        .collect()
+}
+
+/// The call of the trait method of a task, with the context (unless it takes struct fields),
+/// the cloned struct fields and the literal arguments.
+fn task_call_tokens(
+    invocation: &template::Invocation,
+    context_field: Option<&ContextField>,
+    fields: &[TemplateField],
+    template_path_lit: &LitStr,
+) -> Result<TokenStream2> {
+    let method_ident = format_ident!("{}_task", invocation.method);
+    let arguments = invocation
+        .arguments
+        .iter()
+        .map(|argument| match argument {
+            template::Argument::Literal { value, .. } => syn::parse_str::<Lit>(value)
+                .map(ToTokens::into_token_stream)
+                .map_err(|error| Error::Syn(syn::Error::new_spanned(template_path_lit, error))),
+            template::Argument::Field(field) => match context_field {
+                Some(context) if context.ident == field => Ok(context.task_argument()),
+                _ => {
+                    find_task_field(fields, field, invocation, template_path_lit)?;
+                    let ident = format_ident!("{field}");
+
+                    Ok(quote! { self.#ident.clone() })
+                },
+            },
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let context_value = context_field
+        .filter(|_| !takes_fields(invocation))
+        .map(ContextField::task_argument);
+    let arguments = context_value.into_iter().chain(arguments);
+
+    Ok(quote! { Self::#method_ident(#(#arguments),*) })
+}
+
+/// Whether a task of `Render::tasks` reads the context: takes it, or runs in a conditional
+/// section.
+fn uses_context(build: &template::Build, context: &ContextField) -> bool {
+    build.task_invocations().values().any(|invocation| {
+        !takes_fields(invocation)
+            || invocation.arguments.iter().any(
+                |argument| matches!(argument, template::Argument::Field(field) if context.ident == field),
+            )
+    }) || build
+        .task_guards()
+        .values()
+        .any(|guards| guards.iter().all(|guards| !guards.is_empty()))
+}
+
+/// Whether a task takes struct fields, instead of the context.
+fn takes_fields(invocation: &template::Invocation) -> bool {
+    invocation
+        .arguments
+        .iter()
+        .any(|argument| matches!(argument, template::Argument::Field(_)))
+}
+
+/// The struct field passed by name to a task, e.g. `db` in `fn:news(db, user)`.
+fn find_task_field<'a>(
+    fields: &'a [TemplateField],
+    field: &str,
+    invocation: &template::Invocation,
+    template_path_lit: &LitStr,
+) -> Result<&'a TemplateField> {
+    find_field(fields, field).ok_or_else(|| {
+        Error::Syn(syn::Error::new_spanned(
+            template_path_lit,
+            format!(
+                "task '{}' takes the field '{field}', which is not a field of the struct",
+                invocation.method
+            ),
+        ))
+    })
+}
+
+fn final_chunk_tokens(has_html_tag: bool, assets: AssetMode) -> TokenStream2 {
+    match (has_html_tag, assets) {
+        (true, AssetMode::Inline) => {
+            quote! { Some(::htms::Bytes::from_static(br#"<script class="htms-dirty">htmsCleanup();</script></body></html>"#)) }
+        },
+        // no inline script, so a strict Content-Security-Policy does not block the cleanup
+        (true, AssetMode::External) => {
+            quote! { Some(::htms::Bytes::from_static(br#"<htms-cleanup class="htms-dirty"></htms-cleanup></body></html>"#)) }
+        },
+        (false, _) => quote! { None },
+    }
+}
+
+/// `Render::segments`, `Render::template` (and `Render::head` when the compiled template has a
+/// head to flush first), from the segments of the compiled template.
+fn template_tokens(build: &template::Build) -> TokenStream2 {
+    let template = segments_tokens(&build.template_segments());
+    let head = build.head_segments().map(|head| segments_tokens(&head));
+
+    let (head_segments, head_method) = head.map_or_else(
+        || (quote! { None }, quote! {}),
+        |head| {
+            (
+                quote! { Some(#head) },
+                quote! {
+                    fn head() -> Option<::htms::Bytes> {
+                        Self::segments()
+                            .and_then(|segments| segments.head)
+                            .map(|head| {
+                                ::htms::segment::interpolate(head, &::htms::segment::Instance::default())
+                            })
+                    }
+                },
+            )
+        },
+    );
+
+    quote! {
+        fn segments() -> Option<::htms::segment::Segments> {
+            Some(::htms::segment::Segments {
+                head: #head_segments,
+                template: #template,
+            })
+        }
+
+        #head_method
+
+        fn template() -> ::htms::Bytes {
+            Self::segments().map_or_else(::htms::Bytes::new, |segments| {
+                ::htms::segment::interpolate(segments.template, &::htms::segment::Instance::default())
+            })
+        }
+    }
+}
+
+fn segments_tokens(segments: &[Segment<'_>]) -> TokenStream2 {
+    let segments = segments.iter().map(|segment| match segment {
+        Segment::Static(bytes) => {
+            let bytes = Literal::byte_string(bytes);
+            quote! { ::htms::segment::Segment::Static(#bytes) }
+        },
+        Segment::Namespace => quote! { ::htms::segment::Segment::Namespace },
+        Segment::Binding(path) => quote! { ::htms::segment::Segment::Binding(#path) },
+        Segment::If(path) => quote! { ::htms::segment::Segment::If(#path) },
+        Segment::Unless(path) => quote! { ::htms::segment::Segment::Unless(#path) },
+        Segment::Each(path) => quote! { ::htms::segment::Segment::Each(#path) },
+        Segment::EndSection => quote! { ::htms::segment::Segment::EndSection },
+    });
+
+    quote! {
+        {
+            static SEGMENTS: &[::htms::segment::Segment<'static>] = &[#(#segments),*];
+            SEGMENTS
+        }
+    }
+}
+
+fn namespace_tokens(namespace: bool, input_struct_ident: &Ident) -> TokenStream2 {
+    if !namespace {
+        return quote! {};
+    }
+
+    let name = input_struct_ident.to_string();
+
+    quote! {
+        fn namespace(&self) -> Option<::std::string::String> {
+            Some(::htms::render::unique_namespace(#name))
+        }
+    }
+}
+
+/// `Render::bindings`, `Render::conditions` and `Render::collections`, reading the values of the
+/// template from the context field.
+fn context_methods_tokens(
+    build: &template::Build,
+    context_field: Option<&ContextField>,
+    template_path_lit: &LitStr,
+) -> Result<TokenStream2> {
+    let bindings = bindings_tokens(build.bindings(), context_field, template_path_lit)?;
+    let conditions = conditions_tokens(build.conditions(), context_field, template_path_lit)?;
+    let collections = collections_tokens(build.collections(), context_field, template_path_lit)?;
+
+    Ok(quote! {
+        #bindings
+
+        #conditions
+
+        #collections
+    })
+}
+
+/// `Render::bindings`, reading each bound path from the context field.
+///
+/// Paths are spanned to the `#[template = "..."]` literal, so an unknown field or a value that is
+/// not `Display` is reported there by the compiler.
+fn bindings_tokens(
+    bindings: &template::Bindings,
+    context_field: Option<&ContextField>,
+    template_path_lit: &LitStr,
+) -> Result<TokenStream2> {
+    if bindings.is_empty() {
+        return Ok(quote! {});
+    }
+
+    let context = require_context(bindings, context_field, template_path_lit, "binds")?;
+    let values = bindings.iter().map(|path| {
+        let value = context_path_tokens(path, &context.value(), template_path_lit);
+
+        quote_spanned! { template_path_lit.span() =>
+            ::std::string::ToString::to_string(#value)
+        }
+    });
+
+    Ok(quote! {
+        fn bindings(&self) -> ::std::vec::Vec<(&'static str, ::std::string::String)> {
+            vec![#((#bindings, #values),)*]
+        }
+    })
+}
+
+/// `Render::conditions`, reading each `if:` and `unless:` path from the context field.
+///
+/// Like the bindings, paths are spanned to the `#[template = "..."]` literal, so a value that is
+/// not a `bool` or an `Option` is reported there.
+fn conditions_tokens(
+    conditions: &template::Bindings,
+    context_field: Option<&ContextField>,
+    template_path_lit: &LitStr,
+) -> Result<TokenStream2> {
+    if conditions.is_empty() {
+        return Ok(quote! {});
+    }
+
+    let context = require_context(conditions, context_field, template_path_lit, "tests")?;
+    let context = context.value();
+    let values = conditions
+        .iter()
+        .map(|path| condition_tokens(path, &context, template_path_lit));
+
+    Ok(quote! {
+        fn conditions(&self) -> ::std::vec::Vec<(&'static str, bool)> {
+            vec![#((#conditions, #values),)*]
+        }
+    })
+}
+
+/// `Render::collections`, iterating each `each:` path of the context field by reference.
+///
+/// The item bindings and conditions are read from the item like the context ones, so a value that
+/// is not iterable, or an item without the bound fields, is reported on the `#[template = "..."]`
+/// literal.
+fn collections_tokens(
+    collections: &template::Collections,
+    context_field: Option<&ContextField>,
+    template_path_lit: &LitStr,
+) -> Result<TokenStream2> {
+    if collections.is_empty() {
+        return Ok(quote! {});
+    }
+
+    let paths = collections.keys().cloned().collect::<template::Bindings>();
+    let context = require_context(&paths, context_field, template_path_lit, "iterates")?;
+    let context = context.value();
+    let items = collections.iter().map(|(path, collection)| {
+        let collection_value = context_path_tokens(path, &context, template_path_lit);
+        let item = if collection.bindings.is_empty() && collection.conditions.is_empty() {
+            quote! { _ }
+        } else {
+            format_ident!("{}", collection.item, span = template_path_lit.span())
+                .into_token_stream()
+        };
+        let bindings = &collection.bindings;
+        let binding_values = bindings.iter().map(|path| {
+            let value = item_path_tokens(path, template_path_lit);
+
+            quote_spanned! { template_path_lit.span() =>
+                ::std::string::ToString::to_string(#value)
+            }
+        });
+        let conditions = &collection.conditions;
+        let condition_values = conditions.iter().map(|path| {
+            let value = item_path_tokens(path, template_path_lit);
+
+            quote_spanned! { template_path_lit.span() =>
+                ::htms::segment::Condition::holds(#value)
+            }
+        });
+
+        quote_spanned! { template_path_lit.span() =>
+            ::std::iter::IntoIterator::into_iter(#collection_value)
+                .map(|#item| ::htms::segment::Item {
+                    bindings: vec![#((#bindings, #binding_values),)*],
+                    conditions: vec![#((#conditions, #condition_values),)*],
+                })
+                .collect()
+        }
+    });
+    let paths = paths.iter();
+
+    Ok(quote! {
+        fn collections(&self) -> ::std::vec::Vec<(&'static str, ::std::vec::Vec<::htms::segment::Item>)> {
+            vec![#((#paths, #items),)*]
+        }
+    })
+}
+
+/// Whether a task runs: one of its placeholders is in rendered sections.
+fn task_guard_tokens(
+    guards: &[Vec<template::Guard>],
+    context: Option<&TokenStream2>,
+    template_path_lit: &LitStr,
+) -> TokenStream2 {
+    let Some(context) = context else {
+        return quote! { true };
+    };
+
+    if guards.iter().any(Vec::is_empty) {
+        return quote! { true };
+    }
+
+    let placeholders = guards.iter().map(|guards| {
+        let guards = guards.iter().map(|guard| {
+            let condition = condition_tokens(&guard.path, context, template_path_lit);
+
+            if guard.negated {
+                quote! { !#condition }
+            } else {
+                condition
+            }
+        });
+
+        quote! { (#(#guards)&&*) }
+    });
+
+    quote! { #(#placeholders)||* }
+}
+
+fn condition_tokens(
+    path: &str,
+    context: &TokenStream2,
+    template_path_lit: &LitStr,
+) -> TokenStream2 {
+    let value = context_path_tokens(path, context, template_path_lit);
+
+    quote_spanned! { template_path_lit.span() =>
+        ::htms::segment::Condition::holds(#value)
+    }
+}
+
+/// A reference to the value at `path` of the `context` expression, e.g. `&self.context.user.name`.
+fn context_path_tokens(
+    path: &str,
+    context: &TokenStream2,
+    template_path_lit: &LitStr,
+) -> TokenStream2 {
+    let segments = path
+        .split('.')
+        .map(|segment| format_ident!("{}", segment, span = template_path_lit.span()));
+
+    quote_spanned! { template_path_lit.span() =>
+        &#context #(.#segments)*
+    }
+}
+
+/// A reference to the item value at `path`, e.g. `&link.name` in `each:link in links`.
+fn item_path_tokens(path: &str, template_path_lit: &LitStr) -> TokenStream2 {
+    let segments = path
+        .split('.')
+        .map(|segment| format_ident!("{}", segment, span = template_path_lit.span()));
+
+    quote_spanned! { template_path_lit.span() =>
+        &#(#segments).*
+    }
+}
+
+fn require_context<'a>(
+    paths: &template::Bindings,
+    context_field: Option<&'a ContextField>,
+    template_path_lit: &LitStr,
+    verb: &str,
+) -> Result<&'a ContextField> {
+    context_field.ok_or_else(|| {
+        let paths = paths.iter().cloned().collect::<Vec<_>>().join("', '");
+
+        Error::Syn(syn::Error::new_spanned(
+            template_path_lit,
+            format!("the template {verb} '{paths}' but the struct has no #[context] field"),
+        ))
+    })
+}
+
+fn chunk_options_tokens(options: &ChunkOptions) -> TokenStream2 {
+    let kind = match &options.kind {
+        ChunkKind::Content => quote! { Content },
+        ChunkKind::Attribute(name) => quote! { Attribute(::std::string::String::from(#name)) },
+        ChunkKind::Head => quote! { Head },
+    };
+
+    let swap = match options.swap {
+        Swap::Replace => quote! { Replace },
+        Swap::Inner => quote! { Inner },
+        Swap::Append => quote! { Append },
+        Swap::Prepend => quote! { Prepend },
+        Swap::Before => quote! { Before },
+        Swap::After => quote! { After },
+    };
+
+    let selector = option_string_tokens(options.selector.as_deref());
+    let scripts = options.scripts;
+    let parent = option_string_tokens(options.parent.as_deref());
+    let announce = option_string_tokens(options.announce.as_deref());
+    let element_id = option_string_tokens(options.element_id.as_deref());
+    let task = option_string_tokens(options.task.as_deref());
+
+    quote! {
+        ::htms::chunk::ChunkOptions {
+            kind: ::htms::chunk::ChunkKind::#kind,
+            swap: ::htms::chunk::Swap::#swap,
+            selector: #selector,
+            scripts: #scripts,
+            parent: #parent,
+            announce: #announce,
+            element_id: #element_id,
+            task: #task,
+        }
+    }
+}
+
+fn option_string_tokens(value: Option<&str>) -> TokenStream2 {
+    value.map_or_else(
+        || quote! { None },
+        |value| quote! { Some(::std::string::String::from(#value)) },
+    )
+}
+
 #[derive(Debug)]
 struct ContextField {
     ident: Ident,
     ty: Type,
+    /// Passed to the tasks as an `Arc`, see `#[htms(shared_context)]`.
+    shared: bool,
+}
+
+impl ContextField {
+    /// The context field, e.g. `self.context`.
+    fn value(&self) -> TokenStream2 {
+        let ident = &self.ident;
+        quote! { self.#ident }
+    }
+
+    /// The context in `Render::tasks`, where a shared context is moved into the `context` `Arc`.
+    fn task_value(&self) -> TokenStream2 {
+        if self.shared {
+            quote! { context }
+        } else {
+            self.value()
+        }
+    }
+
+    /// The context passed to a task method.
+    fn task_argument(&self) -> TokenStream2 {
+        let value = self.task_value();
+
+        if self.shared {
+            quote! { ::std::sync::Arc::clone(&#value) }
+        } else {
+            quote! { #value.clone() }
+        }
+    }
+
+    /// The type of the context parameter of the task methods.
+    fn task_type(&self) -> TokenStream2 {
+        let ty = &self.ty;
+
+        if self.shared {
+            quote! { ::std::sync::Arc<#ty> }
+        } else {
+            ty.to_token_stream()
+        }
+    }
 }
 
 fn find_context_field(input: &TemplateInput) -> Result<Option<ContextField>> {
@@ -142,6 +761,7 @@ fn find_context_field(input: &TemplateInput) -> Result<Option<ContextField>> {
                 .clone()
                 .unwrap_or_else(|| unreachable!("supports(struct_named) ensures a struct")),
             ty: field.ty.clone(),
+            shared: input.shared_context.is_present(),
         }));
     };
 
@@ -173,6 +793,7 @@ fn find_context_field(input: &TemplateInput) -> Result<Option<ContextField>> {
     Ok(first.ident.clone().map(|ident| ContextField {
         ident,
         ty: first.ty.clone(),
+        shared: input.shared_context.is_present(),
     }))
 }
 
@@ -199,6 +820,39 @@ fn get_template_fields(data: &Data<(), TemplateField>) -> &[TemplateField] {
     }
 }
 
+fn get_template_options(template_input: &TemplateInput) -> Result<template::Options> {
+    let mut options =
+        template::Options::default().with_namespace(template_input.namespace.is_present());
+
+    if let Some(assets) = &template_input.assets {
+        options = options.with_assets(
+            assets
+                .value()
+                .parse()
+                .map_err(|message| syn::Error::new_spanned(assets, message))?,
+        );
+    }
+
+    if let Some(max_include_depth) = template_input.max_include_depth {
+        options = options.with_max_include_depth(max_include_depth);
+    }
+
+    if let Some(max_include_size) = template_input.max_include_size {
+        options = options.with_max_include_size(max_include_size);
+    }
+
+    Ok(options)
+}
+
+/// Templates root, from the `HTMS_TEMPLATE_ROOT` env var (relative to the crate) or the crate itself.
+fn get_template_root(crate_path: &Path) -> Result<PathBuf> {
+    match env::var(TEMPLATE_ROOT_VAR) {
+        Ok(root) => Ok(crate_path.join(root)),
+        Err(VarError::NotPresent) => Ok(crate_path.into()),
+        Err(error) => Err(error.into()),
+    }
+}
+
 fn get_template_path_lit(template_input: &TemplateInput) -> Result<LitStr> {
     let Some(template_attribute) = find_attribute(&template_input.attrs, "template") else {
         return Err(Error::Syn(syn::Error::new_spanned(
@@ -16,16 +16,56 @@ use syn::{DeriveInput, parse_macro_input};
 ///
 /// # Attributes
 /// - `#[template = "path/to/file.html"]` (required)
-///   Path to the HTML template, resolved relative to `CARGO_MANIFEST_DIR`.
+///   Path to the HTML template, resolved relative to the template root: `CARGO_MANIFEST_DIR`, or
+///   the `HTMS_TEMPLATE_ROOT` env var (itself relative to `CARGO_MANIFEST_DIR`) when set.
+///   Includes and `wrap:` layouts cannot leave the template root, `include:/partials/nav.html`
+///   is resolved from it.
 /// - `#[context]` (optional)
 ///   Marks the field used as *context*. If not provided, a field named
-///   `context` is used. The context type **must be `Clone`**.
+///   `context` is used. The context type **must be `Clone`**, unless `#[htms(shared_context)]`.
+///   Context bindings, `data-htms="text:title"` (element content) and
+///   `data-htms-attr-href="user.link"` (attribute value), are read from this field with
+///   `ToString` and escaped into the template when rendering.
+///   Conditional sections, `data-htms="if:user.is_admin"` and `data-htms="unless:user"`, are
+///   rendered or omitted per render from a `bool` or `Option` of this field, the tasks of the
+///   omitted placeholders do not run.
+///   Repeated sections, `data-htms="each:links"` (item named `item`) or
+///   `data-htms="each:link in menu.links"`, are written once per item of an iterable field of
+///   this field, item bindings and conditions such as `text:link.name` are read from each item.
 ///   If both are present, `#[context]` takes precedence.
+/// - `#[htms(assets = "inline" | "external")]` (optional)
+///   How the chunk runtime is emitted. `"inline"` (default) embeds the script and style in
+///   the page, `"external"` references hashed files with Subresource Integrity attributes,
+///   served by `htms::axum::assets_router` (feature = "axum").
+/// - `#[htms(namespace)]` (optional)
+///   Prefix the chunk ids with a prefix unique to each render instance (e.g. `Card-3-stats`),
+///   so the template can appear several times in a page without id collisions. The `id` of the
+///   placeholders marked `data-htms-target="id"` is prefixed too.
+/// - `#[htms(shared_context)]` (optional)
+///   Move the context into an `Arc` once per render and pass an `Arc<Context>` to the task
+///   methods, instead of a clone of the context per task, the context type need not be `Clone`.
+/// - `#[htms(max_include_depth = 16)]` (optional)
+///   Maximum number of nested `include:` levels, includes are resolved relative to the including
+///   file and a cycle between them is a compile-time error.
+/// - `#[htms(max_include_size = 1048576)]` (optional)
+///   Maximum size of an included file, in bytes.
 ///
 /// # Generated items
-/// - `impl htms_core::Render for YourType`
+/// - `impl htms_core::Render for YourType`, the compiled template is embedded as `&'static [u8]`
+///   segments (`Render::segments`) interleaved with the namespace, context binding, conditional
+///   and repeated section holes
 /// - `pub trait YourTypeRender { /* default hooks for tasks/final_chunk */ }`
 ///   Implement this trait for your type to provide async tasks or a final chunk.
+///   Tasks of placeholders marked `data-htms-await` return an `htms::task::Output`, they are
+///   awaited before the template is sent and can set the response status and headers.
+///   Awaited `head:` placeholders are inlined in the `<head>` by `render_with_parts`, which holds
+///   the head until the critical tasks are done; `render` sends the head first and streams them.
+///   Literal arguments, `data-htms="fn:user_card(42)"` (parameters `arg0`, `arg1`, ...) or
+///   `data-htms-arg-id="42"` (parameter `id`), become typed parameters after the context: `i64`,
+///   `f64`, `&'static str`, `bool`, `char`, or the literal suffix (`42u32`). Each distinct list of
+///   arguments runs as a task of its own.
+///   Fields of the struct, `data-htms="fn:news(db, user)"`, are passed by name instead of the
+///   context, each task cloning only the fields it takes: `news_task(db: Pool, user: User)`.
 ///
 /// # Example: template
 /// ```html
@@ -83,12 +123,28 @@ use syn::{DeriveInput, parse_macro_input};
 /// # Errors
 /// This macro emits compile-time errors if:
 /// - `#[template = \"...\"]` is missing or not a string literal,
+/// - `#[htms(assets = \"...\")]` is not a known asset mode,
+/// - the template or one of its includes holds the reserved text `%htms:`,
+/// - an `include:` is part of a cycle or nested deeper than `max_include_depth`,
+/// - an `include:` escapes the template root (through `..` or a symlink) or is larger than
+///   `max_include_size`,
 /// - multiple fields are marked `#[context]`,
-/// - the chosen context field type does not implement `Clone`.
+/// - `#[htms(shared_context)]` is set but the struct has no context field,
+/// - the template has context bindings but the struct has no context field, or a bound path is
+///   not a `Display` field of the context,
+/// - the template has conditional sections but the struct has no context field, or a condition
+///   path is not a `bool` or an `Option` field of the context,
+/// - a `head:` placeholder marked `data-htms-await` is not in the `<head>`,
+/// - the invocations of a task method pass different arguments, an argument is neither a literal
+///   nor a field of the struct, or a field is passed twice,
+/// - an `if:`, `unless:` or `each:` section encloses the `</head>` (e.g. on `<html>`),
+/// - an `each:` section is nested in another one, holds a task placeholder, or its path is not a
+///   field of the context iterable by reference,
+/// - the chosen context field type does not implement `Clone`, without `#[htms(shared_context)]`.
 ///
 /// # Panics
 /// The macro itself does not panic at runtime; it fails at compile-time with diagnostics if misused.
-#[proc_macro_derive(Template, attributes(template, context))]
+#[proc_macro_derive(Template, attributes(template, context, htms))]
 pub fn template_derive(input: TokenStream) -> TokenStream {
     derive::template(&parse_macro_input!(input as DeriveInput))
         .unwrap_or_else(derive::Error::into_compile_error)
@@ -0,0 +1,53 @@
+//! DOM events dispatched by the chunk runtime.
+//!
+//! The event names and their `detail` payloads are a stable contract: client code (charts,
+//! tooltips, analytics...) can rely on them to initialize widgets once a fragment lands.
+//!
+//! | Event                  | Dispatched on                               | Cancelable | `detail`                    |
+//! |------------------------|---------------------------------------------|------------|-----------------------------|
+//! | [`BEFORE_SWAP`]        | the element about to be swapped             | yes        | `{ target, task, element }` |
+//! | [`AFTER_SWAP`]         | the new element (or `document` if none)     | no         | `{ target, task, element }` |
+//! | [`COMPLETE`]           | `document`                                  | no         | `{ pending }`               |
+//!
+//! - `task` is the task method of the placeholder (`news` for `fn:news`, `user_card` for
+//!   `fn:user_card(42)`), the same for every instance of a namespaced template.
+//! - `target` is the chunk id, the `data-htms` value of the placeholder: the task method, with
+//!   an index for the tasks called with arguments (`user_card-0`) and prefixed per instance in
+//!   namespaced templates (`Dashboard-0-news`).
+//! - `element` is the first element of the streamed fragment, or `null` for text-only output.
+//! - attribute chunks (`data-htms-attr`) dispatch both swap events on each patched element, with
+//!   `element` set to that element and an extra `attribute` field holding the attribute name.
+//! - head chunks (`head:`) dispatch both swap events on `document.head`, with `element` set to it.
+//! - `pending` lists the ids of the chunks dropped because their target was never found.
+//!
+//! All events bubble, so a single listener on `document` sees every chunk.
+//!
+//! # Example
+//! ```js
+//! document.addEventListener('htms:after-swap', (event) => {
+//!     if (event.detail.task === 'sales_chart') {
+//!         renderChart(event.detail.element);
+//!     }
+//! });
+//!
+//! document.addEventListener('htms:before-swap', (event) => {
+//!     if (userIsEditing) {
+//!         event.preventDefault(); // keep the placeholder, drop the chunk
+//!     }
+//! });
+//! ```
+
+/// Dispatched before a chunk is swapped into the page.
+///
+/// Calling `preventDefault()` drops the chunk and keeps the placeholder untouched, the chunks
+/// whose `data-htms-parent` is the dropped chunk are dropped too.
+pub const BEFORE_SWAP: &str = "htms:before-swap";
+
+/// Dispatched after a chunk has been swapped into the page.
+pub const AFTER_SWAP: &str = "htms:after-swap";
+
+/// Dispatched once the stream is done, when the final chunk cleans up the runtime.
+///
+/// It always fires: the chunks still waiting for their target are dropped and listed in
+/// `pending`.
+pub const COMPLETE: &str = "htms:complete";
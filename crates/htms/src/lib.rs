@@ -2,5 +2,7 @@
 #![warn(clippy::all, clippy::pedantic, clippy::nursery, clippy::cargo)]
 #![doc = include_str!("../readme.md")]
 
+pub mod events;
+
 pub use htms_core::*;
 pub use htms_derive::Template;